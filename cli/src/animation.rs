@@ -0,0 +1,167 @@
+//! Animated GIF recording of search progress.
+//!
+//! Every `stride` steps the recorder snapshots `GraphSearch::nodes()` on top
+//! of the map and appends it as a frame, in the colours of a legend.
+
+use search::graph::{ GraphSearch, Node2d, NodeState };
+use search::map::legend::Legend;
+use search::map::{ Field, Map, Position, MAX_WEIGHT };
+use std::fs::File;
+use std::io::BufWriter;
+
+const PASSABLE: u8 = 0;
+const IMPASSABLE: u8 = 1;
+const VISITED: u8 = 2;
+const FRONTIER: u8 = 3;
+const PATH: u8 = 4;
+const START: u8 = 5;
+const GOAL: u8 = 6;
+const BACKWARD_VISITED: u8 = 7;
+const BACKWARD_FRONTIER: u8 = 8;
+/// Palette index of terrain weight 1; weights up to `MAX_WEIGHT` follow.
+const WEIGHTS: u8 = 9;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// Search steps between consecutive frames.
+    pub stride: usize,
+    /// Frame delay in hundredths of a second.
+    pub delay: u16,
+    /// Every map field is drawn as a `scale` x `scale` square.
+    pub scale: usize
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { stride: 50, delay: 5, scale: 1 }
+    }
+}
+
+pub struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    options: Options,
+    width: usize,
+    background: Vec<u8>,
+    endpoints: Vec<(Position, u8)>
+}
+
+impl Recorder {
+
+    pub fn create(dest: &str, map: &Map, legend: &Legend, options: Options)
+            -> Result<Recorder, gif::EncodingError> {
+        let scale = options.scale.max(1);
+        let too_big = || std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                             "scaled map does not fit in a GIF frame");
+        let width = to_u16(map.width * scale).ok_or_else(too_big)?;
+        let height = to_u16(map.height * scale).ok_or_else(too_big)?;
+        let file = BufWriter::new(File::create(dest)?);
+        let mut encoder = gif::Encoder::new(file, width, height, &palette(legend))?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        let background = map.fields.iter()
+            .map(|field| match *field {
+                Field::Impassable => IMPASSABLE,
+                Field::Weighted(w) => WEIGHTS + w.clamp(1, MAX_WEIGHT) - 1,
+                _ => PASSABLE
+            })
+            .collect();
+        let mut endpoints: Vec<_> = map.start().into_iter().map(|pos| (pos, START)).collect();
+        endpoints.extend(map.goals().into_iter().map(|pos| (pos, GOAL)));
+        Ok (Recorder { encoder,
+                       options: Options { scale, ..options },
+                       width: map.width,
+                       background,
+                       endpoints })
+    }
+
+    /// Append a frame showing the current state of `search`.
+    pub fn frame<S>(&mut self, search: &S) -> Result<(), gif::EncodingError>
+        where S: GraphSearch<Node2d>
+    {
+        let mut cells = self.background.clone();
+        for Node2d((x, y), state) in search.nodes() {
+            cells[y * self.width + x] = match state {
+                NodeState::Visited => VISITED,
+                NodeState::Frontier => FRONTIER,
+                NodeState::Path => PATH,
+                NodeState::BackwardVisited => BACKWARD_VISITED,
+                NodeState::BackwardFrontier => BACKWARD_FRONTIER
+            };
+        }
+        for &((x, y), index) in self.endpoints.iter() {
+            cells[y * self.width + x] = index;
+        }
+        let pixels = upscale(&cells, self.width, self.options.scale);
+        let scale = self.options.scale;
+        let height = cells.len() / self.width;
+        let mut frame = gif::Frame::from_indexed_pixels((self.width * scale) as u16,
+                                                        (height * scale) as u16,
+                                                        &pixels, None);
+        frame.delay = self.options.delay;
+        self.encoder.write_frame(&frame)
+    }
+
+}
+
+/// Step `search` to completion, recording a frame every `stride` steps
+/// and a final one showing the outcome.
+pub fn record<S>(search: &mut S, recorder: &mut Recorder) -> Result<(), gif::EncodingError>
+    where S: GraphSearch<Node2d>
+{
+    let stride = recorder.options.stride.max(1);
+    recorder.frame(search)?;
+    while !search.result().is_over() {
+        for _ in 0 .. stride {
+            search.step();
+            if search.result().is_over()
+                { break }
+        }
+        recorder.frame(search)?;
+    }
+    Ok (())
+}
+
+fn palette(legend: &Legend) -> Vec<u8> {
+    let mut colors = vec![legend.color(Field::Passable), legend.color(Field::Impassable),
+                          legend.visited, legend.frontier, legend.path,
+                          legend.color(Field::Start), legend.color(Field::Goal),
+                          legend.backward_visited, legend.backward_frontier];
+    colors.extend((1 ..= MAX_WEIGHT).map(|w| legend.color(Field::Weighted(w))));
+    colors.into_iter().flat_map(|(r, g, b)| vec![r, g, b]).collect()
+}
+
+fn upscale(cells: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(cells.len() * scale * scale);
+    for row in cells.chunks(width) {
+        let line: Vec<u8> = row.iter()
+            .flat_map(|&index| std::iter::repeat_n(index, scale))
+            .collect();
+        for _ in 0 .. scale
+            { pixels.extend_from_slice(&line) }
+    }
+    pixels
+}
+
+fn to_u16(n: usize) -> Option<u16> {
+    if n <= u16::MAX as usize { Some (n as u16) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn upscale_repeats_rows_and_columns() {
+        assert_eq!(upscale(&[1, 2, 3, 4], 2, 2),
+                   vec![1, 1, 2, 2,
+                        1, 1, 2, 2,
+                        3, 3, 4, 4,
+                        3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn palette_covers_every_weight() {
+        assert_eq!(palette(&Legend::default()).len(), 3 * (WEIGHTS + MAX_WEIGHT) as usize);
+    }
+
+}
@@ -1,57 +1,758 @@
+extern crate clap;
 extern crate env_logger;
+extern crate gif;
 #[macro_use] extern crate log;
 extern crate png;
-extern crate rustc_serialize;
+extern crate serde_json;
 
 extern crate search;
 
-use search::graph::{ SearchState, GraphSearch };
-use search::{ map, Search };
+mod animation;
+mod report;
 
-#[derive(Debug)]
+use animation::Recorder;
+use clap::{ App, AppSettings, Arg, ArgMatches, SubCommand };
+use search::graph::{ GraphSearch, Node2d, NodeState };
+use search::heuristic::{ self, Heuristic };
+use search::map::legend::Legend;
+use search::map::png::{ Format, Transparency };
+use search::map::{ movingai, Connectivity, Map, Position, WorldShape };
+use search::{ map, MapField, Search };
+use std::collections::{ BTreeMap, HashMap };
+use std::error::Error;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+
+const ALGORITHMS: &[&str] = &["bfs", "astar", "dijkstra", "greedy", "dfs", "iddfs",
+                               "idastar", "bibfs", "biastar", "jps", "jps-plus",
+                               "theta", "lazy-theta", "lpastar", "dstar-lite"];
+const HEURISTICS: &[&str] = &["zero", "manhattan", "chebyshev", "octile",
+                              "euclidean", "rounded-euclidean"];
+const CONNECTIVITIES: &[&str] = &["4", "8", "8-no-squeezing", "8-no-corner-cutting"];
+const SHAPES: &[&str] = &["bounded", "wrap-horizontally", "wrap-vertically", "torus"];
+const FORMATS: &[&str] = &["text", "json"];
+const TRANSPARENCIES: &[&str] = &["impassable", "passable", "ignore"];
+const MAP_KINDS: &[&str] = &["open", "maze", "maze-prim", "maze-kruskal", "dungeon",
+                             "caves", "obstacles", "terrain"];
+
+/// Largest difference between a found and a scenario's optimal path length counted as a match.
+const LENGTH_TOLERANCE: f64 = 1e-4;
+
+/// Exit code of runs which completed, but found the map or the search wanting.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code of invalid invocations.
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Clone, Copy, Debug)]
 enum Method {
-    BFS
+    BFS,
+    AStar,
+    Dijkstra,
+    Greedy,
+    Dfs,
+    Iddfs,
+    IDAStar,
+    BidirectionalBFS,
+    BidirectionalAStar,
+    JumpPoint,
+    JumpPointPlus,
+    ThetaStar,
+    LazyThetaStar,
+    LifelongPlanningAStar,
+    DStarLite
+}
+
+impl Method {
+    fn by_name(name: &str) -> Option<Method> {
+        match name {
+            "bfs" => Some (Method::BFS),
+            "astar" => Some (Method::AStar),
+            "dijkstra" => Some (Method::Dijkstra),
+            "greedy" => Some (Method::Greedy),
+            "dfs" => Some (Method::Dfs),
+            "iddfs" => Some (Method::Iddfs),
+            "idastar" => Some (Method::IDAStar),
+            "bibfs" => Some (Method::BidirectionalBFS),
+            "biastar" => Some (Method::BidirectionalAStar),
+            "jps" => Some (Method::JumpPoint),
+            "jps-plus" => Some (Method::JumpPointPlus),
+            "theta" => Some (Method::ThetaStar),
+            "lazy-theta" => Some (Method::LazyThetaStar),
+            "lpastar" => Some (Method::LifelongPlanningAStar),
+            "dstar-lite" => Some (Method::DStarLite),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Method::BFS => "bfs",
+            Method::AStar => "astar",
+            Method::Dijkstra => "dijkstra",
+            Method::Greedy => "greedy",
+            Method::Dfs => "dfs",
+            Method::Iddfs => "iddfs",
+            Method::IDAStar => "idastar",
+            Method::BidirectionalBFS => "bibfs",
+            Method::BidirectionalAStar => "biastar",
+            Method::JumpPoint => "jps",
+            Method::JumpPointPlus => "jps-plus",
+            Method::ThetaStar => "theta",
+            Method::LazyThetaStar => "lazy-theta",
+            Method::LifelongPlanningAStar => "lpastar",
+            Method::DStarLite => "dstar-lite"
+        }
+    }
+
+    fn uses_heuristic(&self) -> bool {
+        match *self {
+            Method::AStar | Method::Greedy | Method::IDAStar | Method::BidirectionalAStar => true,
+            Method::BFS | Method::Dijkstra | Method::Dfs | Method::Iddfs
+                | Method::BidirectionalBFS | Method::JumpPoint | Method::JumpPointPlus
+                | Method::ThetaStar | Method::LazyThetaStar
+                | Method::LifelongPlanningAStar | Method::DStarLite => false
+        }
+    }
+
+    /// Whether the method can search maps like this one at all.
+    fn supports(&self, map: &search::map::Map) -> bool {
+        match *self {
+            Method::JumpPoint | Method::JumpPointPlus => search::jps::check_map(map).is_ok(),
+            Method::ThetaStar | Method::LazyThetaStar => search::theta::check_map(map).is_ok(),
+            Method::DStarLite => map.start().len() <= 1,
+            _ => true
+        }
+    }
+
+    /// Whether the method takes exponential time on open maps,
+    /// so it's benchmarked only when asked for.
+    fn is_exponential(&self) -> bool {
+        matches!(*self, Method::IDAStar | Method::Iddfs)
+    }
+}
+
+/// An error concerning the named file.
+#[derive(Debug)]
+struct FileError {
+    path: String,
+    source: Box<dyn Error>
+}
+
+impl FileError {
+    fn new(path: &str, source: impl Into<Box<dyn Error>>) -> FileError {
+        FileError { path: path.to_string(), source: source.into() }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.source)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some (self.source.as_ref())
+    }
 }
 
 fn main() {
     env_logger::init();
-    let args : Vec<String> = std::env::args().collect();
-    if args.len() < 3
-        { panic!("expected SRC and DST args") }
-    let ref arg_src = args[1];
-    let ref arg_dst = args[2];
-    //let img = png::load_png(&Path::new(arg_src)).unwrap();
-    //let map = map::from_png(&img);
-    let map = map::png::load(arg_src);
-    let method = Method::BFS;
-    match do_search(&map, method) {
-        Err (e) => panic!("error: {:?}", e),
-        Ok (result) => map::png::save(&map, &result, arg_dst.clone())
-    }
-}
-
-fn do_search(map: &search::map::Map, method: Method)
-        -> Result<search::Search, search::Error> {
-    let start = map.start();
-    let goals = map.goals();
+    let matches = app().get_matches_safe().unwrap_or_else(|e| {
+        if e.use_stderr() {
+            eprintln!("{}", e.message);
+            std::process::exit(EXIT_USAGE)
+        }
+        println!("{}", e.message);
+        std::process::exit(0)
+    });
+    let result = match matches.subcommand() {
+        ("solve", Some (m)) => solve(m),
+        ("render", Some (m)) => render(m),
+        ("bench", Some (m)) => bench(m),
+        ("validate", Some (m)) => validate(m),
+        ("generate", Some (m)) => generate(m),
+        ("scenarios", Some (m)) => scenarios(m),
+        _ => unreachable!("clap requires a subcommand")
+    };
+    if let Err (e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(EXIT_FAILURE)
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let map_arg = Arg::with_name("MAP")
+        .help("PNG map: black is passable, blue impassable, green start and red goal")
+        .required(true);
+    App::new("cli")
+        .about("Searches for paths on PNG maps")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("solve")
+                    .about("Finds a path and prints it along with search statistics")
+                    .arg(map_arg.clone())
+                    .arg(format_arg())
+                    .args(&search_args())
+                    .args(&movement_args()))
+        .subcommand(SubCommand::with_name("render")
+                    .about("Finds a path and draws it; a .gif OUTPUT records the whole search")
+                    .arg(map_arg.clone())
+                    .arg(Arg::with_name("OUTPUT").required(true))
+                    .arg(format_arg())
+                    .args(&search_args())
+                    .args(&movement_args())
+                    .args(&animation_args()))
+        .subcommand(SubCommand::with_name("bench")
+                    .about("Compares running time and effort of search algorithms")
+                    .arg(map_arg.clone())
+                    .arg(Arg::with_name("algorithm")
+                         .long("algorithm").short("a").takes_value(true)
+                         .multiple(true).number_of_values(1)
+                         .possible_values(ALGORITHMS)
+                         .help("Algorithm to include; by default all of them which support the map, \
+                                except the exponential idastar and iddfs"))
+                    .args(&heuristic_args())
+                    .arg(format_arg())
+                    .arg(Arg::with_name("runs")
+                         .long("runs").takes_value(true).default_value("10")
+                         .validator(is_positive))
+                    .args(&movement_args()))
+        .subcommand(SubCommand::with_name("scenarios")
+                    .about("Runs MovingAI benchmark scenarios and checks found path lengths")
+                    .arg(Arg::with_name("SCEN").required(true)
+                         .help("MovingAI .scen file"))
+                    .arg(Arg::with_name("maps")
+                         .long("maps").takes_value(true).value_name("DIR")
+                         .help("Directory with the .map files; the scenario file's one by default"))
+                    .arg(format_arg())
+                    .args(&search_args()))
+        .subcommand(SubCommand::with_name("validate")
+                    .about("Checks that the map loads and its goal is reachable from its start")
+                    .arg(map_arg)
+                    .arg(format_arg())
+                    .args(&movement_args()))
+        .subcommand(SubCommand::with_name("generate")
+                    .about("Writes a generated map; a .txt or .map OUTPUT is written as text")
+                    .arg(Arg::with_name("OUTPUT").required(true))
+                    .arg(Arg::with_name("kind")
+                         .long("kind").takes_value(true).default_value("open")
+                         .possible_values(MAP_KINDS))
+                    .arg(Arg::with_name("seed")
+                         .long("seed").takes_value(true).default_value("0")
+                         .validator(is_seed)
+                         .help("The same seed always gives the same map"))
+                    .arg(Arg::with_name("density")
+                         .long("density").takes_value(true).default_value("0.3")
+                         .validator(is_probability)
+                         .help("Share of impassable fields of obstacles maps"))
+                    .arg(Arg::with_name("width")
+                         .long("width").takes_value(true).default_value("64")
+                         .validator(is_positive))
+                    .arg(Arg::with_name("height")
+                         .long("height").takes_value(true).default_value("64")
+                         .validator(is_positive)))
+}
+
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format").short("f").takes_value(true)
+        .default_value("text")
+        .possible_values(FORMATS)
+        .help("Print results as text or as a JSON document")
+}
+
+fn heuristic_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("heuristic")
+             .long("heuristic").takes_value(true)
+             .default_value("octile")
+             .possible_values(HEURISTICS)
+             .help("Heuristic used by informed algorithms"),
+         Arg::with_name("weight")
+             .long("weight").takes_value(true)
+             .default_value("1")
+             .validator(is_weight)
+             .help("Factor the heuristic is multiplied by; above 1 trades optimality for speed")]
+}
+
+fn search_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![Arg::with_name("algorithm")
+                            .long("algorithm").short("a").takes_value(true)
+                            .default_value("bfs")
+                            .possible_values(ALGORITHMS)];
+    args.extend(heuristic_args());
+    args
+}
+
+fn movement_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("connectivity")
+             .long("connectivity").short("c").takes_value(true)
+             .default_value("8")
+             .possible_values(CONNECTIVITIES),
+         Arg::with_name("shape")
+             .long("shape").takes_value(true)
+             .default_value("bounded")
+             .possible_values(SHAPES),
+         Arg::with_name("legend")
+             .long("legend").takes_value(true).value_name("FILE")
+             .help("Colours of map fields and search progress"),
+         Arg::with_name("transparent")
+             .long("transparent").takes_value(true)
+             .possible_values(TRANSPARENCIES)
+             .help("What transparent pixels of the map stand for, overriding the legend; \
+                    ignore goes by colour alone"),
+         Arg::with_name("start")
+             .long("start").takes_value(true).value_name("X,Y")
+             .multiple(true).number_of_values(1)
+             .validator(is_position)
+             .help("Start position replacing the ones marked on the map"),
+         Arg::with_name("goal")
+             .long("goal").takes_value(true).value_name("X,Y")
+             .multiple(true).number_of_values(1)
+             .validator(is_position)
+             .help("Goal position replacing the ones marked on the map")]
+}
+
+fn animation_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("stride")
+             .long("stride").takes_value(true).default_value("50")
+             .validator(is_positive)
+             .help("Search steps per animation frame"),
+         Arg::with_name("delay")
+             .long("delay").takes_value(true).default_value("5")
+             .validator(is_positive)
+             .help("Animation frame delay in hundredths of a second"),
+         Arg::with_name("scale")
+             .long("scale").takes_value(true).default_value("1")
+             .validator(is_positive)
+             .help("Size of the square drawn for each map field")]
+}
+
+fn is_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok (n) if n > 0 => Ok (()),
+        _ => Err (format!("expected a positive number, got {}", value))
+    }
+}
+
+fn is_seed(value: String) -> Result<(), String> {
+    value.parse::<u64>().map(|_| ())
+        .map_err(|_| format!("expected a non-negative number, got {}", value))
+}
+
+fn is_probability(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok (p) if (0.0 ..= 1.0).contains(&p) => Ok (()),
+        _ => Err (format!("expected a number between 0 and 1, got {}", value))
+    }
+}
+
+fn is_weight(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok (w) if w.is_finite() && w >= 1.0 => Ok (()),
+        _ => Err (format!("expected a number of at least 1, got {}", value))
+    }
+}
+
+fn is_position(value: String) -> Result<(), String> {
+    parse_position(&value).map(|_| ())
+}
+
+fn parse_position(value: &str) -> Result<Position, String> {
+    let mut coords = value.splitn(2, ',').map(|c| c.trim().parse::<usize>());
+    match (coords.next(), coords.next()) {
+        (Some (Ok (x)), Some (Ok (y))) => Ok ((x, y)),
+        _ => Err (format!("expected X,Y coordinates, got {}", value))
+    }
+}
+
+/// Only call for arguments with a validator or a default value.
+fn number(m: &ArgMatches, name: &str) -> usize {
+    m.value_of(name).and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| unreachable!("{} is validated by clap", name))
+}
+
+fn positions(m: &ArgMatches, name: &str) -> Vec<Position> {
+    m.values_of(name)
+        .map(|values| values.filter_map(|v| parse_position(v).ok()).collect())
+        .unwrap_or_default()
+}
+
+fn heuristic(m: &ArgMatches) -> Box<dyn Heuristic> {
+    let base = m.value_of("heuristic").and_then(heuristic::by_name)
+        .unwrap_or_else(|| Box::new(heuristic::Octile));
+    match weight(m) {
+        Some (w) => Box::new(heuristic::Weighted(w, base)),
+        None => base
+    }
+}
+
+/// The heuristic weight, unless it's the neutral 1.
+fn weight(m: &ArgMatches) -> Option<f64> {
+    m.value_of("weight").and_then(|w| w.parse::<f64>().ok())
+        .filter(|&w| w != 1.0)
+}
+
+fn method(m: &ArgMatches) -> Method {
+    m.value_of("algorithm").and_then(Method::by_name).unwrap_or(Method::BFS)
+}
+
+fn json_format(m: &ArgMatches) -> bool {
+    m.value_of("format") == Some ("json")
+}
+
+/// The legend given on the command line, or the default one.
+fn legend(m: &ArgMatches) -> Result<Legend, Box<dyn Error>> {
+    let mut legend = match m.value_of("legend") {
+        Some (source) => Legend::load(source).map_err(|e| FileError::new(source, e))?,
+        None => Legend::default()
+    };
+    match m.value_of("transparent") {
+        Some ("impassable") => legend.transparent = Transparency::Impassable,
+        Some ("passable") => legend.transparent = Transparency::Passable,
+        Some ("ignore") => legend.transparent = Transparency::Ignore,
+        _ => ()
+    }
+    Ok (legend)
+}
+
+fn load_map(m: &ArgMatches) -> Result<Map, Box<dyn Error>> {
+    load_map_and_format(m, &legend(m)?).map(|(map, _)| map)
+}
+
+/// Load the map and apply movement rules and endpoint overrides given on the command line.
+/// The format of the map image is kept for writing results.
+fn load_map_and_format(m: &ArgMatches, legend: &Legend) -> Result<(Map, Format), Box<dyn Error>> {
+    let source = m.value_of("MAP").unwrap_or_default();
+    let (mut map, format) = map::png::load_with(source, legend)
+        .map_err(|e| FileError::new(source, e))?;
+    if let Some (c) = m.value_of("connectivity").and_then(Connectivity::by_name) {
+        map.rules.connectivity = c
+    }
+    if let Some (s) = m.value_of("shape").and_then(WorldShape::by_name) {
+        map.rules.shape = s
+    }
+    let start = positions(m, "start");
+    if !start.is_empty() {
+        map.set_start(&start)?
+    }
+    let goals = positions(m, "goal");
+    if !goals.is_empty() {
+        map.set_goals(&goals)?
+    }
+    Ok ((map, format))
+}
+
+type Outcome = Result<Search, Box<dyn Error>>;
+
+/// A map with the format and legend it was loaded with.
+type Loaded = (Map, Format, Legend);
+
+/// Load the legend and the map and search it;
+/// the map is missing from the result if it couldn't be loaded.
+fn load_and_search(m: &ArgMatches,
+                   recorder: impl FnOnce(&Map, &Legend) -> Result<Option<Recorder>, Box<dyn Error>>)
+        -> (Option<Loaded>, Outcome) {
+    let legend = match legend(m) {
+        Ok (legend) => legend,
+        Err (e) => return (None, Err (e))
+    };
+    let (map, format) = match load_map_and_format(m, &legend) {
+        Ok (loaded) => loaded,
+        Err (e) => return (None, Err (e))
+    };
+    let result = recorder(&map, &legend).and_then(|mut recorder| {
+        do_search(&map, method(m), heuristic(m), recorder.as_mut())
+    });
+    (Some ((map, format, legend)), result)
+}
+
+/// Print the JSON report of a search and pass its result on.
+fn print_json(m: &ArgMatches, map: Option<&Map>, result: Outcome) -> Outcome {
+    let parameters = report::parameters(m, method(m).uses_heuristic());
+    let mut report = report::search(parameters, map, &result);
+    report["map"] = serde_json::json!(m.value_of("MAP"));
+    println!("{}", report);
+    result
+}
+
+fn solve(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let (loaded, result) = load_and_search(m, |_, _| Ok (None));
+    if json_format(m) {
+        return print_json(m, loaded.as_ref().map(|(map, _, _)| map), result).map(|_| ())
+    }
+    let result = result?;
+    println!("{}", result.stats);
+    // Listed from the start to the goal, like in the JSON report.
+    for path in result.paths.iter() {
+        let path = match loaded {
+            Some ((ref map, _, _)) => search::path::normalise(map, path.clone()),
+            None => path.clone()
+        };
+        let steps: Vec<String> = path.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+        println!("path: {}", steps.join(" "))
+    }
+    Ok (())
+}
+
+fn render(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let output = m.value_of("OUTPUT").unwrap_or_default();
+    // A .gif output records the whole search instead of just its outcome.
+    let animate = output.ends_with(".gif");
+    let (loaded, result) = load_and_search(m, |map, legend| {
+        if !animate {
+            return Ok (None)
+        }
+        let options = animation::Options { stride: number(m, "stride"),
+                                           delay: number(m, "delay").min(u16::MAX as usize) as u16,
+                                           scale: number(m, "scale") };
+        let recorder = Recorder::create(output, map, legend, options)
+            .map_err(|e| FileError::new(output, e))?;
+        Ok (Some (recorder))
+    });
+    let result = if json_format(m) {
+        print_json(m, loaded.as_ref().map(|(map, _, _)| map), result)?
+    } else {
+        let result = result?;
+        println!("{}", result.stats);
+        result
+    };
+    // Results are written in the format of the map image.
+    if let (false, Some ((map, format, legend))) = (animate, loaded) {
+        map::png::save_as(&map, &result, output, format, &legend)
+            .map_err(|e| FileError::new(output, e))?
+    }
+    Ok (())
+}
+
+fn bench(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let map = load_map(m)?;
+    let runs = number(m, "runs");
+    let methods: Vec<Method> = match m.values_of("algorithm") {
+        Some (names) => names.filter_map(Method::by_name).collect(),
+        None => ALGORITHMS.iter()
+            .filter_map(|name| Method::by_name(name))
+            .filter(|method| method.supports(&map) && !method.is_exponential())
+            .collect()
+    };
+    let json = json_format(m);
+    if !json {
+        println!("{:<10} {:>10} {:>10} {:>12} {:>14}",
+                 "algorithm", "expanded", "length", "cost", "mean time");
+    }
+    let mut reports = vec![];
+    for method in methods {
+        let mut total = Duration::from_secs(0);
+        let mut last = None;
+        for _ in 0 .. runs {
+            let started = Instant::now();
+            let result = do_search(&map, method, heuristic(m), None)?;
+            total += started.elapsed();
+            last = Some (result)
+        }
+        if let Some (result) = last {
+            let stats = &result.stats;
+            let mean = total / runs as u32;
+            if json {
+                reports.push(serde_json::json!({
+                    "algorithm": method.name(),
+                    "heuristic": if method.uses_heuristic() { m.value_of("heuristic") } else { None },
+                    "weight": if method.uses_heuristic() { m.value_of("weight") } else { None },
+                    "runs": runs,
+                    "mean_time_secs": mean.as_secs_f64(),
+                    "stats": report::stats(stats)
+                }));
+            } else {
+                println!("{:<10} {:>10} {:>10} {:>12.3} {:>14?}",
+                         method.name(), stats.expanded,
+                         stats.path_length, stats.path_cost, mean);
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::Value::Array(reports));
+    }
+    Ok (())
+}
+
+fn validate(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source = m.value_of("MAP").unwrap_or_default();
+    let result = load_map(m).and_then(|map| {
+        search::bfs(map.clone()).run(None)?;
+        Ok (map)
+    });
+    if json_format(m) {
+        let mut report = serde_json::json!({ "map": source, "valid": result.is_ok() });
+        if let Err (ref e) = result {
+            report["error"] = report::error(e.as_ref());
+        }
+        println!("{}", report);
+        return result.map(|_| ())
+    }
+    let map = result?;
+    println!("{}: ok, {}x{}, {} start(s), {} goal(s)", source,
+             map.width, map.height, map.start().len(), map.goals().len());
+    Ok (())
+}
+
+/// Outcome of the scenarios of a single bucket.
+#[derive(Default)]
+struct Bucket {
+    scenarios: usize,
+    mismatched: usize,
+    failed: usize,
+    time: Duration
+}
+
+fn scenarios(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source = m.value_of("SCEN").unwrap_or_default();
+    let scenarios = movingai::load_scenarios(source)
+        .map_err(|e| FileError::new(source, e))?;
+    let base = match m.value_of("maps") {
+        Some (dir) => PathBuf::from(dir),
+        None => Path::new(source).parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+    let method = method(m);
+    let mut maps: HashMap<String, Map> = HashMap::new();
+    let mut buckets: BTreeMap<usize, Bucket> = BTreeMap::new();
+    for scenario in scenarios.iter() {
+        if !maps.contains_key(&scenario.map) {
+            maps.insert(scenario.map.clone(), load_scenario_map(&base, &scenario.map)?);
+        }
+        let mut map = maps[&scenario.map].clone();
+        scenario.check_map(&map)?;
+        map.set_start(&[scenario.start])?;
+        map.set_goals(&[scenario.goal])?;
+        let started = Instant::now();
+        let result = do_search(&map, method, heuristic(m), None);
+        let bucket = buckets.entry(scenario.bucket).or_default();
+        bucket.scenarios += 1;
+        bucket.time += started.elapsed();
+        match result {
+            Ok (ref result) if (result.stats.path_cost - scenario.optimal_length).abs() > LENGTH_TOLERANCE => {
+                info!("{:?}: found {} instead of {}", scenario, result.stats.path_cost,
+                      scenario.optimal_length);
+                bucket.mismatched += 1
+            },
+            Ok (_) => (),
+            Err (e) => {
+                info!("{:?}: {}", scenario, e);
+                bucket.failed += 1
+            }
+        }
+    }
+    if json_format(m) {
+        let reports: Vec<_> = buckets.iter().map(|(number, bucket)| serde_json::json!({
+            "bucket": number,
+            "scenarios": bucket.scenarios,
+            "mismatched": bucket.mismatched,
+            "failed": bucket.failed,
+            "mean_time_secs": bucket.time.as_secs_f64() / bucket.scenarios as f64
+        })).collect();
+        println!("{}", serde_json::json!({
+            "scenarios": source,
+            "parameters": report::parameters(m, method.uses_heuristic()),
+            "buckets": reports
+        }));
+    } else {
+        println!("{:>6} {:>10} {:>10} {:>8} {:>14}",
+                 "bucket", "scenarios", "mismatched", "failed", "mean time");
+        for (number, bucket) in buckets.iter() {
+            println!("{:>6} {:>10} {:>10} {:>8} {:>14?}", number, bucket.scenarios,
+                     bucket.mismatched, bucket.failed, bucket.time / bucket.scenarios as u32);
+        }
+    }
+    let wrong: usize = buckets.values().map(|b| b.mismatched + b.failed).sum();
+    if wrong > 0 {
+        return Err (format!("{} of {} scenarios did not find an optimal path",
+                            wrong, scenarios.len()).into())
+    }
+    Ok (())
+}
+
+/// Scenario files name maps with a directory prefix, which might not match the local layout.
+fn load_scenario_map(base: &Path, name: &str) -> Result<Map, Box<dyn Error>> {
+    let mut path = base.join(name);
+    if !path.exists() {
+        if let Some (file_name) = Path::new(name).file_name() {
+            path = base.join(file_name)
+        }
+    }
+    let path = path.to_string_lossy();
+    Ok (movingai::load(&path).map_err(|e| FileError::new(&path, e))?)
+}
+
+fn generate(m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    use search::map::generate::{ self, MazeAlgorithm };
+    let output = m.value_of("OUTPUT").unwrap_or_default();
+    let (width, height) = (number(m, "width"), number(m, "height"));
+    let seed = m.value_of("seed").and_then(|v| v.parse().ok()).unwrap_or_default();
+    let density = m.value_of("density").and_then(|v| v.parse().ok()).unwrap_or_default();
+    let map = match m.value_of("kind").unwrap_or_default() {
+        "maze" => generate::maze(width, height, MazeAlgorithm::RecursiveBacktracker, seed),
+        "maze-prim" => generate::maze(width, height, MazeAlgorithm::Prim, seed),
+        "maze-kruskal" => generate::maze(width, height, MazeAlgorithm::Kruskal, seed),
+        "dungeon" => generate::dungeon(width, height, seed),
+        "caves" => generate::caves(width, height, seed),
+        "obstacles" => generate::obstacles(width, height, density, seed),
+        "terrain" => generate::terrain(width, height, seed),
+        _ => generate::open(width, height)
+    };
+    let written = if output.ends_with(".txt") {
+        map::ascii::save(&map, output, &map::ascii::Glyphs::default())
+    } else if output.ends_with(".map") {
+        movingai::save(&map, output)
+    } else {
+        let mut image = map::png::map_to_png(&map);
+        map::png::draw_points(&map.start(), map::png::GREEN, &mut image);
+        map::png::draw_points(&map.goals(), map::png::RED, &mut image);
+        map::png::write_image(&mut image, output)
+    };
+    written.map_err(|e| FileError::new(output, e))?;
+    Ok (())
+}
+
+fn do_search(map: &search::map::Map, method: Method, heuristic: Box<dyn Heuristic>,
+             recorder: Option<&mut Recorder>)
+        -> Result<search::Search, Box<dyn Error>> {
     info!("searching with {:?}", method);
     match method {
-        Method::BFS => do_bfs(map.clone())
-            .map(|path| Search { start: start,
-                                 goals: goals,
-                                 paths: vec![path],
-                                 visited: vec![] })
+        Method::BFS => complete(map, search::bfs(map.clone()), recorder),
+        Method::AStar => complete(map, search::astar_with(map.clone(), heuristic), recorder),
+        Method::Dijkstra => complete(map, search::dijkstra(map.clone()), recorder),
+        Method::Greedy => complete(map, search::greedy_with(map.clone(), heuristic), recorder),
+        Method::Dfs => complete(map, search::dfs(map.clone()), recorder),
+        Method::Iddfs => complete(map, search::iddfs(map.clone()), recorder),
+        Method::IDAStar => complete(map, search::idastar_with(map.clone(), heuristic), recorder),
+        Method::BidirectionalBFS => complete(map, search::bidirectional_bfs(map.clone()), recorder),
+        Method::BidirectionalAStar =>
+            complete(map, search::bidirectional_astar_with(map.clone(), heuristic), recorder),
+        Method::JumpPoint => complete(map, search::jps(map.clone()), recorder),
+        Method::JumpPointPlus => complete(map, search::jps_plus(map.clone()), recorder),
+        Method::ThetaStar => complete(map, search::theta_star(map.clone()), recorder),
+        Method::LazyThetaStar => complete(map, search::lazy_theta_star(map.clone()), recorder),
+        Method::LifelongPlanningAStar => complete(map, search::lpa_star(map.clone()), recorder),
+        Method::DStarLite => complete(map, search::dstar_lite(map.clone()), recorder)
     }
 }
 
-fn do_bfs(map: search::map::Map)
-        -> Result<search::Path, search::Error> {
-    let mut state = search::bfs(map);
-    while !state.result.is_over() {
-        state.step();
-    }
-    match state.result {
-        SearchState::Finished(path) => Ok(path),
-        _ => Err(search::Error::GoalUnreachable)
+fn complete<S>(map: &search::map::Map, mut state: S, recorder: Option<&mut Recorder>)
+        -> Result<search::Search, Box<dyn Error>>
+    where S: GraphSearch<Node2d, Node=MapField>
+{
+    if let Some (recorder) = recorder {
+        animation::record(&mut state, recorder)?
     }
+    let path = state.run(None)?;
+    let visited = state.nodes()
+        .filter_map(|Node2d(pos, node_state)| match node_state {
+            NodeState::Visited | NodeState::BackwardVisited => Some (pos),
+            _ => None
+        })
+        .collect();
+    Ok (Search { start: map.start(),
+                 goals: map.goals(),
+                 paths: vec![path],
+                 visited,
+                 stats: state.stats().clone() })
 }
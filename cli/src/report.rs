@@ -0,0 +1,149 @@
+//! JSON rendering of search results for consumption by other programs.
+
+use clap::ArgMatches;
+use search::map::{ Map, Position };
+use search::path;
+use search::stats::SearchStats;
+use search::Search;
+use serde_json::{ json, Value };
+use std::error::Error;
+
+/// Algorithm and movement options the search was run with.
+pub fn parameters(m: &ArgMatches, uses_heuristic: bool) -> Value {
+    json!({
+        "algorithm": m.value_of("algorithm"),
+        "heuristic": if uses_heuristic { m.value_of("heuristic") } else { None },
+        "weight": if uses_heuristic { m.value_of("weight") } else { None },
+        "connectivity": m.value_of("connectivity"),
+        "shape": m.value_of("shape")
+    })
+}
+
+/// Describe the map and the outcome of searching it;
+/// the map is missing if it couldn't be loaded.
+/// The path is listed from the start to the goal, although searches return it goal first.
+pub fn search(parameters: Value, map: Option<&Map>,
+              result: &Result<Search, Box<dyn Error>>) -> Value {
+    let mut report = json!({ "parameters": parameters });
+    if let Some (map) = map {
+        report["width"] = json!(map.width);
+        report["height"] = json!(map.height);
+        report["start"] = positions(&map.start());
+        report["goals"] = positions(&map.goals());
+    }
+    match result {
+        Ok (search) => {
+            let path = search.paths.first().cloned().unwrap_or_default();
+            let path = match map {
+                Some (map) => path::normalise(map, path),
+                None => path
+            };
+            report["found"] = json!(true);
+            report["path"] = positions(&path);
+            report["path_length"] = json!(search.stats.path_length);
+            report["path_cost"] = json!(search.stats.path_cost);
+            report["visited"] = json!(search.visited.len());
+            report["stats"] = stats(&search.stats);
+        },
+        Err (e) => {
+            report["found"] = json!(false);
+            report["error"] = error(e.as_ref());
+        }
+    }
+    report
+}
+
+pub fn position((x, y): Position) -> Value {
+    json!([x, y])
+}
+
+pub fn positions(positions: &[Position]) -> Value {
+    Value::Array(positions.iter().cloned().map(position).collect())
+}
+
+pub fn stats(stats: &SearchStats) -> Value {
+    json!({
+        "expanded": stats.expanded,
+        "generated": stats.generated,
+        "max_frontier": stats.max_frontier,
+        "reopened": stats.reopened,
+        "path_length": stats.path_length,
+        "path_cost": stats.path_cost,
+        "wall_time_secs": stats.wall_time.as_secs_f64(),
+        "steps": stats.steps,
+        "thresholds": stats.thresholds
+    })
+}
+
+/// Describe `error` by the kind of the first library error in its chain and its message.
+pub fn error(error: &(dyn Error + 'static)) -> Value {
+    let mut kind = "other";
+    let mut cause = Some (error);
+    while let Some (e) = cause {
+        if let Some (e) = e.downcast_ref::<search::Error>() {
+            kind = error_kind(e);
+            break
+        }
+        cause = e.source();
+    }
+    json!({ "kind": kind, "message": error.to_string() })
+}
+
+fn error_kind(error: &search::Error) -> &'static str {
+    match *error {
+        search::Error::NoStart => "no-start",
+        search::Error::NoGoal => "no-goal",
+        search::Error::MultipleStartsUnsupported => "multiple-starts-unsupported",
+        search::Error::GoalUnreachable => "goal-unreachable",
+        search::Error::Io(_) => "io",
+        search::Error::UnsupportedPng(_) => "unsupported-png",
+        search::Error::Malformed(_) => "malformed",
+        search::Error::OutOfBounds(_) => "out-of-bounds",
+        search::Error::BudgetExhausted => "budget-exhausted",
+        search::Error::UnsupportedMap(_) => "unsupported-map",
+        search::Error::InvalidPath(..) => "invalid-path"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapper(search::Error);
+
+    impl std::fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "map.png: {}", self.0)
+        }
+    }
+
+    impl Error for Wrapper {
+        fn source(&self) -> Option<&(dyn Error + 'static)> { Some (&self.0) }
+    }
+
+    #[test]
+    fn error_kind_is_found_in_the_source_chain() {
+        let value = error(&Wrapper(search::Error::NoGoal));
+        assert_eq!(value["kind"], "no-goal");
+        assert_eq!(value["message"], format!("map.png: {}", search::Error::NoGoal));
+        let other: Box<dyn Error> = "bad".into();
+        assert_eq!(error(other.as_ref())["kind"], "other");
+    }
+
+    #[test]
+    fn paths_go_from_start_to_goal() {
+        use search::map::Field;
+        let mut fields = vec![Field::Passable; 6];
+        fields[0] = Field::Start;
+        fields[5] = Field::Goal;
+        let map = Map::new(3, 2, fields);
+        let result = search::astar(map.start(), map.goals(), &map).map_err(|e| e.into());
+        let report = search(json!({}), Some (&map), &result);
+        let path = report["path"].as_array().unwrap();
+        assert_eq!(Some (&position((0,0))), path.first());
+        assert_eq!(Some (&position((2,1))), path.last());
+    }
+
+}
@@ -0,0 +1,205 @@
+use crate::Error;
+use crate::graph::*;
+use crate::stats::SearchStats;
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::rc::Rc;
+use std::time::Instant;
+
+#[derive(Clone)]
+pub struct AStarSearch<V: SearchNode> {
+    pub result: SearchState<V>,
+    pub frontier: BinaryHeap<Scored<V>>,
+    pub visited: HashSet<V::Id>,
+    pub steps: HashMap<V::Id, V::Id>,
+    pub g_score: HashMap<V::Id, f64>,
+    pub heuristic: Rc<dyn Fn(&V) -> f64>,
+    pub stats: SearchStats
+}
+
+impl<V: SearchNode> AStarSearch<V> {
+
+    pub fn new(start: Vec<V>, heuristic: Rc<dyn Fn(&V) -> f64>) -> AStarSearch<V> {
+        let mut g_score = HashMap::new();
+        let mut frontier = BinaryHeap::new();
+        for node in start {
+            g_score.insert(node.id(), 0.0);
+            frontier.push(Scored(heuristic(&node), node));
+        }
+        let stats = SearchStats { generated: frontier.len(),
+                                  max_frontier: frontier.len(),
+                                  ..SearchStats::default() };
+        AStarSearch { result: SearchState::NotStarted,
+                      frontier,
+                      visited: HashSet::new(),
+                      steps: HashMap::new(),
+                      g_score,
+                      heuristic,
+                      stats }
+    }
+
+    /// Pop the best open node, skipping entries superseded by a cheaper path.
+    fn pop_open(&mut self) -> Option<V> {
+        while let Some (Scored(_, node)) = self.frontier.pop() {
+            if !self.visited.contains(&node.id()) {
+                return Some (node)
+            }
+        }
+        None
+    }
+
+    fn expand(&mut self) {
+        let current = match self.pop_open() {
+            None => {
+                self.result = SearchState::Failed(Error::GoalUnreachable);
+                return
+            },
+            Some (current) => current
+        };
+        self.result = SearchState::InProgress;
+        debug!(target: "astar", "visited: {:?}", self.visited);
+        debug!(target: "astar", "current: {:?}", current.id());
+        debug!(target: "astar", "steps  : {:?}", self.steps);
+        if current.is_goal() {
+            debug!(target: "astar", "goal found: {:?}", current.id());
+            let path = build_path::<V>(&self.steps, current.id());
+            self.stats.path(&path, self.g_score[&current.id()]);
+            self.result = SearchState::Finished(path);
+            return
+        }
+        self.visited.insert(current.id());
+        self.stats.expanded += 1;
+        let g = self.g_score[&current.id()];
+        for next in current.neighbours() {
+            let id = next.id();
+            let tentative_g = g + current.cost(&next);
+            let improved = match self.g_score.get(&id) {
+                None => true,
+                // Tolerate rounding errors of summing up diagonal moves in different orders.
+                Some (&known) => tentative_g < known - 1e-9
+            };
+            if improved {
+                // Only an inconsistent heuristic can lead to a cheaper path to a closed node.
+                if self.visited.remove(&id) {
+                    self.stats.reopened += 1;
+                }
+                let f = tentative_g + (self.heuristic)(&next);
+                self.g_score.insert(id.clone(), tentative_g);
+                self.steps.insert(id, current.id());
+                self.frontier.push(Scored(f, next));
+                self.stats.generated += 1;
+            }
+        }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for AStarSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.visited.iter()
+            .map(|pos| Node2d(pos.pos2d(), NodeState::Visited));
+        let frontier = self.frontier.iter()
+            .filter(move |Scored(_, v)| !self.visited.contains(&v.id()))
+            .map(|Scored(_, v)| Node2d(v.id().pos2d(), NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|pos| Node2d(pos.pos2d(), NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::{ GraphSearch, SearchState };
+    use crate::heuristic::RoundedEuclidean;
+    use crate::map::{ self, Field, Map, WorldShape };
+
+    #[test]
+    fn finds_path_as_short_as_bfs() {
+        let map = map::png::load("../test/fixtures/map2.png").unwrap();
+        let mut bfs = crate::bfs(map.clone());
+        let mut astar = crate::astar_search(map);
+        while !bfs.result.is_over() {
+            bfs.step();
+        }
+        while !astar.result.is_over() {
+            astar.step();
+        }
+        match (bfs.result, astar.result) {
+            (SearchState::Finished(expected), SearchState::Finished(path)) =>
+                assert_eq!(expected.len(), path.len()),
+            _ => panic!("path not found")
+        }
+    }
+
+    #[test]
+    fn dijkstra_prefers_cheap_terrain_over_fewer_moves() {
+        // S9G
+        // ...
+        let map = Map::new(3, 2, vec![Field::Start, Field::Weighted(9), Field::Goal,
+                                      Field::Passable, Field::Passable, Field::Passable]);
+        let mut search = crate::dijkstra(map);
+        while !search.result.is_over() {
+            search.step();
+        }
+        match search.result {
+            SearchState::Finished(path) => assert_eq!(vec![(2,0), (1,1), (0,0)], path),
+            _ => panic!("path not found")
+        }
+    }
+
+    #[test]
+    fn crosses_joined_edges_of_wrapped_world() {
+        // S.#.G
+        let mut map = Map::new(5, 1, vec![Field::Start, Field::Passable, Field::Impassable,
+                                          Field::Passable, Field::Goal]);
+        map.rules.shape = WorldShape::WrapHorizontally;
+        let mut search = crate::astar_search(map);
+        while !search.result.is_over() {
+            search.step();
+        }
+        match search.result {
+            SearchState::Finished(path) => assert_eq!(vec![(4,0), (0,0)], path),
+            _ => panic!("path not found")
+        }
+    }
+
+    #[test]
+    fn stats_count_reopenings_of_inconsistent_heuristic() {
+        let map = map::png::load("../test/fixtures/map5.png").unwrap();
+        let mut consistent = crate::astar_search(map.clone());
+        let path = consistent.run(None).unwrap();
+        assert_eq!(path.len() - 1, consistent.stats.path_length);
+        assert_eq!(0, consistent.stats.reopened);
+        assert!(consistent.stats.generated > consistent.stats.expanded);
+        let mut inconsistent = crate::astar_with(map, RoundedEuclidean);
+        inconsistent.run(None).unwrap();
+        assert!(inconsistent.stats.reopened > 0);
+        assert!((consistent.stats.path_cost - inconsistent.stats.path_cost).abs() < 1e-9);
+    }
+
+}
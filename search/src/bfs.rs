@@ -1,22 +1,36 @@
+use crate::Error;
 use crate::graph::*;
+use crate::stats::SearchStats;
 use std::collections::{ HashMap, HashSet };
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct BFSSearch<V: SearchNode> {
     pub result: SearchState<V>,
     pub frontier: Vec<V>,
     pub visited: HashSet<V::Id>,
-    pub steps: HashMap<V::Id, V::Id>
+    pub steps: HashMap<V::Id, V::Id>,
+    pub g_score: HashMap<V::Id, f64>,
+    pub stats: SearchStats
 }
 
-impl<V: SearchNode> GraphSearch<Node2d> for BFSSearch<V> {
+impl<V: SearchNode> BFSSearch<V> {
 
-    fn step(&mut self) {
-        if self.result.is_over() {
-            return
-        }
+    pub fn new(start: Vec<V>) -> BFSSearch<V> {
+        let stats = SearchStats { generated: start.len(),
+                                  max_frontier: start.len(),
+                                  ..SearchStats::default() };
+        BFSSearch { result: SearchState::NotStarted,
+                    visited: start.iter().map(|node| node.id()).collect(),
+                    g_score: start.iter().map(|node| (node.id(), 0.0)).collect(),
+                    frontier: start,
+                    steps: HashMap::new(),
+                    stats }
+    }
+
+    fn expand(&mut self) {
         if self.frontier.is_empty() {
-            self.result = SearchState::Failed("goal unreachable".to_string());
+            self.result = SearchState::Failed(Error::GoalUnreachable);
             return
         }
         let current = self.frontier.remove(0);
@@ -26,19 +40,48 @@ impl<V: SearchNode> GraphSearch<Node2d> for BFSSearch<V> {
         if current.is_goal() {
             debug!(target: "bfs", "goal found: {:?}", current.id());
             let path = build_path::<V>(&self.steps, current.id());
+            self.stats.path(&path, self.g_score[&current.id()]);
             self.result = SearchState::Finished(path);
             return
         }
+        self.stats.expanded += 1;
         let neighbours = current.neighbours();
         let n_ids: Vec<V::Id> = neighbours.iter().map(|n| n.id()).collect();
         debug!(target: "bfs", "allowed: {:?}", n_ids);
+        let g = self.g_score[&current.id()];
         for next in neighbours.iter() {
             if !self.visited.contains(&next.id()) {
                 self.frontier.push(next.clone());
                 self.visited.insert(next.id());
                 self.steps.insert(next.id(), current.id());
+                self.g_score.insert(next.id(), g + current.cost(next));
+                self.stats.generated += 1;
             }
         }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for BFSSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
     }
 
     fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
@@ -0,0 +1,294 @@
+use crate::Error;
+use crate::graph::*;
+use crate::stats::SearchStats;
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Estimate of the remaining length from a node to the roots of the other half.
+type Estimate<V> = Rc<dyn Fn(&V) -> f64>;
+
+/// One of the two searches of a bidirectional search.
+#[derive(Clone)]
+pub struct Half<V: SearchNode> {
+    pub frontier: BinaryHeap<Scored<V>>,
+    pub visited: HashSet<V::Id>,
+    pub steps: HashMap<V::Id, V::Id>,
+    /// Length of the best known path from the roots of this half:
+    /// the number of moves for breadth-first search, the cost otherwise.
+    pub g_score: HashMap<V::Id, f64>,
+    /// Cost of the best known path from the roots of this half.
+    pub cost: HashMap<V::Id, f64>,
+    pub heuristic: Option<Estimate<V>>
+}
+
+impl<V: SearchNode> Half<V> {
+
+    fn new(roots: Vec<V>, heuristic: Option<Estimate<V>>) -> Half<V> {
+        let mut half = Half { frontier: BinaryHeap::new(),
+                              visited: HashSet::new(),
+                              steps: HashMap::new(),
+                              g_score: HashMap::new(),
+                              cost: HashMap::new(),
+                              heuristic };
+        for node in roots {
+            half.g_score.insert(node.id(), 0.0);
+            half.cost.insert(node.id(), 0.0);
+            half.frontier.push(Scored(half.estimate(&node), node));
+        }
+        half
+    }
+
+    fn estimate(&self, node: &V) -> f64 {
+        self.heuristic.as_ref().map_or(0.0, |h| h(node))
+    }
+
+    /// Lowest f-score on the frontier; a lower bound on paths through unexpanded nodes.
+    fn bound(&self) -> f64 {
+        self.frontier.peek().map_or(f64::INFINITY, |Scored(f, _)| *f)
+    }
+
+    fn nodes(&self, visited: NodeState, frontier: NodeState) -> impl Iterator<Item=Node2d> + '_ {
+        let expanded = self.visited.iter()
+            .map(move |pos| Node2d(pos.pos2d(), visited));
+        let open = self.frontier.iter()
+            .map(move |Scored(_, v)| Node2d(v.id().pos2d(), frontier));
+        expanded.chain(open)
+    }
+
+    /// Pop the best open node, skipping entries superseded by a shorter path.
+    fn pop_open(&mut self) -> Option<V> {
+        while let Some (Scored(_, node)) = self.frontier.pop() {
+            if !self.visited.contains(&node.id()) {
+                return Some (node)
+            }
+        }
+        None
+    }
+
+}
+
+/// Two searches growing towards each other, one from the start and one from the goals,
+/// until the shortest path through a node reached by both is proven to be the shortest path.
+///
+/// The graph must be undirected: the neighbours of a node are also the nodes it's reached from.
+#[derive(Clone)]
+pub struct BidirectionalSearch<V: SearchNode> {
+    pub result: SearchState<V>,
+    /// Search from the start nodes.
+    pub forward: Half<V>,
+    /// Search from the goal nodes.
+    pub backward: Half<V>,
+    /// Whether to minimise the number of moves rather than their cost.
+    pub unit_moves: bool,
+    /// Node joining the shortest path found so far and that path's length.
+    pub meeting: Option<(V::Id, f64)>,
+    pub stats: SearchStats
+}
+
+impl<V: SearchNode> BidirectionalSearch<V> {
+
+    /// Breadth-first searches from both ends, finding a path with the fewest moves.
+    pub fn breadth_first(start: Vec<V>, goals: Vec<V>) -> BidirectionalSearch<V> {
+        BidirectionalSearch::new(Half::new(start, None), Half::new(goals, None), true)
+    }
+
+    /// A* from both ends, finding the cheapest path;
+    /// `to_goals` and `to_start` estimate the remaining cost of either direction.
+    pub fn astar(start: Vec<V>, goals: Vec<V>,
+                 to_goals: Estimate<V>, to_start: Estimate<V>)
+            -> BidirectionalSearch<V> {
+        BidirectionalSearch::new(Half::new(start, Some (to_goals)),
+                                 Half::new(goals, Some (to_start)),
+                                 false)
+    }
+
+    fn new(forward: Half<V>, backward: Half<V>, unit_moves: bool) -> BidirectionalSearch<V> {
+        let roots = forward.frontier.len() + backward.frontier.len();
+        let meeting = forward.g_score.keys()
+            .find(|id| backward.g_score.contains_key(id))
+            .map(|id| (id.clone(), 0.0));
+        BidirectionalSearch { result: SearchState::NotStarted,
+                              forward,
+                              backward,
+                              unit_moves,
+                              meeting,
+                              stats: SearchStats { generated: roots,
+                                                   max_frontier: roots,
+                                                   ..SearchStats::default() } }
+    }
+
+    /// Whether no path through the unexpanded nodes can beat the best one found.
+    fn is_proven(&self, best: f64) -> bool {
+        let (forward, backward) = (self.forward.bound(), self.backward.bound());
+        if self.forward.heuristic.is_none() && self.backward.heuristic.is_none() {
+            forward + backward >= best - 1e-9
+        } else {
+            forward.max(backward) >= best - 1e-9
+        }
+    }
+
+    fn finish(&mut self, meeting: V::Id) {
+        debug!(target: "bidirectional", "paths meet at {:?}", meeting);
+        let mut path = build_path::<V>(&self.backward.steps, meeting.clone());
+        path.reverse();
+        path.extend(build_path::<V>(&self.forward.steps, meeting.clone()).into_iter().skip(1));
+        let cost = self.forward.cost[&meeting] + self.backward.cost[&meeting];
+        self.stats.path(&path, cost);
+        self.result = SearchState::Finished(path);
+    }
+
+    fn expand(&mut self) {
+        if let Some ((meeting, best)) = self.meeting.clone() {
+            if self.is_proven(best) {
+                return self.finish(meeting)
+            }
+        }
+        // Grow the smaller frontier, so that both searches cover similar areas;
+        // take turns while they're equal.
+        let (forward_size, backward_size) = (self.forward.frontier.len(), self.backward.frontier.len());
+        let backwards = backward_size < forward_size
+            || backward_size == forward_size && self.stats.expanded % 2 == 1;
+        let (half, other) = if backwards { (&mut self.backward, &self.forward) }
+                            else { (&mut self.forward, &self.backward) };
+        let current = match half.pop_open() {
+            Some (current) => current,
+            None => {
+                match self.meeting.clone() {
+                    Some ((meeting, _)) => self.finish(meeting),
+                    None => self.result = SearchState::Failed(Error::GoalUnreachable)
+                }
+                return
+            }
+        };
+        self.result = SearchState::InProgress;
+        debug!(target: "bidirectional", "current: {:?} (backwards: {})", current.id(), backwards);
+        half.visited.insert(current.id());
+        self.stats.expanded += 1;
+        let (g, cost) = (half.g_score[&current.id()], half.cost[&current.id()]);
+        for next in current.neighbours() {
+            let id = next.id();
+            // Moves of the backward search are taken in reverse.
+            let step_cost = if backwards { next.cost(&current) } else { current.cost(&next) };
+            let tentative_g = g + if self.unit_moves { 1.0 } else { step_cost };
+            let improved = match half.g_score.get(&id) {
+                None => true,
+                Some (&known) => tentative_g < known - 1e-9
+            };
+            if !improved {
+                continue
+            }
+            if half.visited.remove(&id) {
+                self.stats.reopened += 1;
+            }
+            let f = tentative_g + half.estimate(&next);
+            half.g_score.insert(id.clone(), tentative_g);
+            half.cost.insert(id.clone(), cost + step_cost);
+            half.steps.insert(id.clone(), current.id());
+            half.frontier.push(Scored(f, next));
+            self.stats.generated += 1;
+            if let Some (&other_g) = other.g_score.get(&id) {
+                let length = tentative_g + other_g;
+                if self.meeting.as_ref().is_none_or(|(_, best)| length < *best - 1e-9) {
+                    self.meeting = Some ((id, length));
+                }
+            }
+        }
+        self.stats.frontier_size(self.forward.frontier.len() + self.backward.frontier.len());
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for BidirectionalSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let nodes = self.forward.nodes(NodeState::Visited, NodeState::Frontier)
+            .chain(self.backward.nodes(NodeState::BackwardVisited, NodeState::BackwardFrontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|pos| Node2d(pos.pos2d(), NodeState::Path));
+            Box::new( nodes.chain(path) )
+        } else {
+            Box::new( nodes )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::{ GraphSearch, Node2d, NodeState };
+    use crate::map::{ self, ascii, Map };
+
+    fn assert_contiguous(map: &Map, path: &[(usize, usize)]) {
+        assert_eq!(map.goals()[0], path[0]);
+        assert_eq!(map.start()[0], path[path.len() - 1]);
+        for step in path.windows(2) {
+            let (dx, dy) = (step[0].0 as isize - step[1].0 as isize,
+                            step[0].1 as isize - step[1].1 as isize);
+            assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+        }
+    }
+
+    #[test]
+    fn finds_paths_as_short_as_unidirectional_search() {
+        for fixture in &["map2", "map4", "map5"] {
+            let map = map::png::load(&format!("../test/fixtures/{}.png", fixture)).unwrap();
+            let mut bfs = crate::bfs(map.clone());
+            let mut bidirectional_bfs = crate::bidirectional_bfs(map.clone());
+            let path = bidirectional_bfs.run(None).unwrap();
+            assert_eq!(bfs.run(None).unwrap().len(), path.len(), "{}", fixture);
+            assert_contiguous(&map, &path);
+            let mut astar = crate::astar_search(map.clone());
+            astar.run(None).unwrap();
+            let mut bidirectional_astar = crate::bidirectional_astar(map.clone());
+            let path = bidirectional_astar.run(None).unwrap();
+            assert!((astar.stats.path_cost - bidirectional_astar.stats.path_cost).abs() < 1e-9,
+                    "{}", fixture);
+            assert_contiguous(&map, &path);
+        }
+    }
+
+    #[test]
+    fn shows_both_frontiers() {
+        let map = ascii::parse("S.......G\n", &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::bidirectional_bfs(map);
+        search.step();
+        search.step();
+        let nodes: Vec<Node2d> = search.nodes().collect();
+        assert!(nodes.iter().any(|Node2d(pos, state)| *pos == (0,0) && *state == NodeState::Visited));
+        assert!(nodes.iter().any(|Node2d(pos, state)| *pos == (8,0) && *state == NodeState::BackwardVisited));
+        assert!(nodes.iter().any(|Node2d(_, state)| *state == NodeState::BackwardFrontier));
+        assert_eq!(9, search.run(None).unwrap().len());
+        assert_eq!(8, search.stats.path_length);
+    }
+
+    #[test]
+    fn fails_when_halves_never_meet() {
+        let map = ascii::parse("S.#.G\n", &ascii::Glyphs::default()).unwrap();
+        match crate::bidirectional_astar(map).run(None) {
+            Err (crate::Error::GoalUnreachable) => (),
+            _ => panic!("expected GoalUnreachable")
+        }
+    }
+
+}
@@ -0,0 +1,273 @@
+use crate::Error;
+use crate::graph::*;
+use crate::stats::SearchStats;
+use std::collections::{ HashMap, HashSet };
+use std::time::Instant;
+
+/// Depth-first search, optionally giving up on paths longer than a depth limit.
+#[derive(Clone)]
+pub struct DFSSearch<V: SearchNode> {
+    pub result: SearchState<V>,
+    /// Stack of nodes to expand, each with the number of moves it takes to reach it.
+    pub frontier: Vec<(V, usize)>,
+    pub visited: HashSet<V::Id>,
+    pub steps: HashMap<V::Id, V::Id>,
+    pub g_score: HashMap<V::Id, f64>,
+    /// Fewest moves a node has been reached with.
+    pub depth: HashMap<V::Id, usize>,
+    /// Deepest nodes to expand, if any.
+    pub limit: Option<usize>,
+    /// Whether nodes at the depth limit were left unexpanded,
+    /// so that a deeper search might still find a goal.
+    pub cut_off: bool,
+    pub stats: SearchStats
+}
+
+impl<V: SearchNode> DFSSearch<V> {
+
+    pub fn new(start: Vec<V>) -> DFSSearch<V> {
+        let stats = SearchStats { generated: start.len(),
+                                  max_frontier: start.len(),
+                                  ..SearchStats::default() };
+        DFSSearch { result: SearchState::NotStarted,
+                    visited: HashSet::new(),
+                    g_score: start.iter().map(|node| (node.id(), 0.0)).collect(),
+                    depth: start.iter().map(|node| (node.id(), 0)).collect(),
+                    // Reversed, so that the first start node is popped first.
+                    frontier: start.into_iter().rev().map(|node| (node, 0)).collect(),
+                    steps: HashMap::new(),
+                    limit: None,
+                    cut_off: false,
+                    stats }
+    }
+
+    /// Don't expand nodes more than `limit` moves away from the start.
+    ///
+    /// A node first reached by a long detour is expanded again once a shorter way to it is found,
+    /// otherwise the limit could hide goals within it.
+    pub fn depth_limited(start: Vec<V>, limit: usize) -> DFSSearch<V> {
+        DFSSearch { limit: Some (limit), ..DFSSearch::new(start) }
+    }
+
+    /// Whether a stack entry was superseded by a later one for the same node.
+    fn is_stale(&self, node: &V, depth: usize) -> bool {
+        match self.limit {
+            None => self.visited.contains(&node.id()),
+            Some (_) => depth > self.depth[&node.id()]
+        }
+    }
+
+    fn expand(&mut self) {
+        let (current, depth) = loop {
+            match self.frontier.pop() {
+                None => {
+                    self.result = SearchState::Failed(Error::GoalUnreachable);
+                    return
+                },
+                Some ((node, depth)) => if !self.is_stale(&node, depth) {
+                    break (node, depth)
+                }
+            }
+        };
+        self.result = SearchState::InProgress;
+        debug!(target: "dfs", "current: {:?} at depth {}", current.id(), depth);
+        if current.is_goal() {
+            debug!(target: "dfs", "goal found: {:?}", current.id());
+            let path = build_path::<V>(&self.steps, current.id());
+            self.stats.path(&path, self.g_score[&current.id()]);
+            self.result = SearchState::Finished(path);
+            return
+        }
+        if !self.visited.insert(current.id()) {
+            self.stats.reopened += 1;
+        }
+        if self.limit == Some (depth) {
+            self.cut_off = true;
+            return
+        }
+        self.stats.expanded += 1;
+        let g = self.g_score[&current.id()];
+        // Pushed in reverse, so that neighbours are explored in their usual order.
+        for next in current.neighbours().into_iter().rev() {
+            let unseen = match self.limit {
+                None => !self.visited.contains(&next.id()),
+                Some (_) => self.depth.get(&next.id()).is_none_or(|&d| depth + 1 < d)
+            };
+            if unseen {
+                self.steps.insert(next.id(), current.id());
+                self.g_score.insert(next.id(), g + current.cost(&next));
+                self.depth.insert(next.id(), depth + 1);
+                self.frontier.push((next, depth + 1));
+                self.stats.generated += 1;
+            }
+        }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for DFSSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.visited.iter()
+            .map(|pos| Node2d(pos.pos2d(), NodeState::Visited));
+        let frontier = self.frontier.iter()
+            .map(|(v, _)| Node2d(v.id().pos2d(), NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|pos| Node2d(pos.pos2d(), NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+/// Depth-limited searches with ever larger limits, finding paths with the fewest moves.
+///
+/// Unlike textbook IDDFS it doesn't keep only a stack in memory: each iteration also remembers
+/// the visited nodes and the steps and depth it reached them with, so memory grows with the
+/// number of nodes within the limit, as it does for BFS.
+/// Each iteration starts over from scratch, which takes a step of its own.
+#[derive(Clone)]
+pub struct IterativeDeepeningSearch<V: SearchNode> {
+    pub start: Vec<V>,
+    /// Search of the current iteration; its stats cover all iterations so far.
+    pub current: DFSSearch<V>
+}
+
+impl<V: SearchNode> IterativeDeepeningSearch<V> {
+
+    pub fn new(start: Vec<V>) -> IterativeDeepeningSearch<V> {
+        let mut current = DFSSearch::depth_limited(start.clone(), 0);
+        current.stats.thresholds.push(0.0);
+        IterativeDeepeningSearch { current, start }
+    }
+
+    /// Depth limit of the current iteration.
+    pub fn limit(&self) -> usize {
+        self.current.limit.unwrap_or_default()
+    }
+
+    fn deepen(&mut self) {
+        let limit = self.limit() + 1;
+        debug!(target: "dfs", "deepening to {}", limit);
+        let mut next = DFSSearch::depth_limited(self.start.clone(), limit);
+        next.stats = SearchStats { generated: self.current.stats.generated + self.start.len(),
+                                   ..self.current.stats.clone() };
+        next.stats.thresholds.push(limit as f64);
+        next.result = SearchState::InProgress;
+        self.current = next;
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for IterativeDeepeningSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        match self.current.result {
+            SearchState::Failed(Error::GoalUnreachable) if self.current.cut_off => {
+                let started = Instant::now();
+                self.deepen();
+                self.current.stats.step(started);
+            },
+            _ => self.current.step()
+        }
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        match self.current.result {
+            SearchState::Failed(Error::GoalUnreachable) if self.current.cut_off => &SearchState::InProgress,
+            ref result => result
+        }
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.current.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        self.current.nodes()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::GraphSearch;
+    use crate::map::{ ascii, Connectivity };
+    use crate::Error;
+
+    const DETOUR: &str = "S....\n.###.\n.#G..\n.....\n";
+
+    fn map() -> crate::map::Map {
+        let mut map = ascii::parse(DETOUR, &ascii::Glyphs::default()).unwrap();
+        map.rules.connectivity = Connectivity::Four;
+        map
+    }
+
+    #[test]
+    fn dfs_follows_the_first_branch() {
+        let mut search = crate::dfs(map());
+        let path = search.run(None).unwrap();
+        assert_eq!((2,2), path[0]);
+        assert_eq!((0,0), path[path.len() - 1]);
+        for step in path.windows(2) {
+            let (dx, dy) = (step[0].0 as isize - step[1].0 as isize,
+                            step[0].1 as isize - step[1].1 as isize);
+            assert_eq!(1, dx.abs() + dy.abs());
+        }
+    }
+
+    #[test]
+    fn depth_limit_hides_distant_goals() {
+        let mut search = crate::depth_limited_dfs(map(), 5);
+        match search.run(None) {
+            Err (Error::GoalUnreachable) => assert!(search.cut_off),
+            _ => panic!("expected GoalUnreachable")
+        }
+        let mut search = crate::depth_limited_dfs(map(), 6);
+        assert_eq!(7, search.run(None).unwrap().len());
+    }
+
+    #[test]
+    fn iterative_deepening_finds_fewest_moves() {
+        let mut search = crate::iddfs(map());
+        let path = search.run(None).unwrap();
+        assert_eq!(7, path.len());
+        assert_eq!(6, search.limit());
+        assert_eq!(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0], search.stats().thresholds);
+        let mut walled = map();
+        walled[(2,2)] = crate::map::Field::Impassable;
+        walled[(4,0)] = crate::map::Field::Goal;
+        walled[(1,0)] = crate::map::Field::Impassable;
+        walled[(0,1)] = crate::map::Field::Impassable;
+        match crate::iddfs(walled).run(None) {
+            Err (Error::GoalUnreachable) => (),
+            _ => panic!("expected GoalUnreachable")
+        }
+    }
+
+}
@@ -1,3 +1,6 @@
+use crate::Error;
+use crate::stats::SearchStats;
+use std::cmp::Ordering;
 use std::collections::{ HashMap };
 use std::fmt::Debug;
 use std::hash::Hash;
@@ -12,11 +15,38 @@ pub trait SearchNode: Clone + Eq + Hash {
     fn id(&self) -> Self::Id;
     fn is_goal(&self) -> bool;
     fn neighbours(&self) -> Vec<Self>;
+
+    /// Cost of moving from this node to `next`, one of its `neighbours()`.
+    fn cost(&self, _next: &Self) -> f64 { 1.0 }
 }
 
 pub trait GraphSearch<NodeId> {
+    type Node: SearchNode;
+
     fn step(&mut self);
     fn nodes(&self) -> Box<dyn Iterator<Item=NodeId> + '_>;
+    fn result(&self) -> &SearchState<Self::Node>;
+    fn stats(&self) -> &SearchStats;
+
+    /// Step until the search is over, but no more than `budget` times if one is given.
+    fn run(&mut self, budget: Option<usize>)
+            -> Result<Vec<<Self::Node as SearchNode>::Id>, Error> {
+        let mut taken = 0;
+        while !self.result().is_over() {
+            if let Some (budget) = budget {
+                if taken >= budget {
+                    return Err (Error::BudgetExhausted)
+                }
+            }
+            self.step();
+            taken += 1;
+        }
+        match self.result() {
+            SearchState::Finished(path) => Ok (path.clone()),
+            SearchState::Failed(e) => Err (e.clone()),
+            _ => unreachable!()
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -24,7 +54,7 @@ pub enum SearchState<V: SearchNode> {
     NotStarted,
     InProgress,
     Finished(Vec<V::Id>),
-    Failed(String)
+    Failed(Error)
 }
 
 impl<V: SearchNode> SearchState<V> {
@@ -38,10 +68,15 @@ impl<V: SearchNode> SearchState<V> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeState {
     Visited,
     Frontier,
-    Path
+    Path,
+    /// Expanded by the search growing from the goals of a bidirectional search.
+    BackwardVisited,
+    /// On the frontier of the search growing from the goals of a bidirectional search.
+    BackwardFrontier
 }
 
 pub struct Node2d(pub (usize, usize), pub NodeState);
@@ -61,3 +96,23 @@ pub fn build_path<Node: SearchNode>(steps: &HashMap<Node::Id, Node::Id>,
     }
     path
 }
+
+/// Priority frontier entry: a binary heap of `Scored` pops the lowest score first.
+#[derive(Clone)]
+pub struct Scored<V>(pub f64, pub V);
+
+impl<V> PartialEq for Scored<V> {
+    fn eq(&self, other: &Self) -> bool { self.0 == other.0 }
+}
+
+impl<V> Eq for Scored<V> {}
+
+impl<V> PartialOrd for Scored<V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some (self.cmp(other)) }
+}
+
+impl<V> Ord for Scored<V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
@@ -1,57 +1,102 @@
+use crate::Error;
 use crate::graph::*;
-use std::collections::{ HashMap, HashSet };
+use crate::stats::SearchStats;
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::rc::Rc;
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct GreedySearch<V: SearchNode> {
     pub result: SearchState<V>,
+    pub frontier: BinaryHeap<Scored<V>>,
     pub visited: HashSet<V::Id>,
-    pub steps: HashMap<V::Id, V::Id>
-
-    let map = map.clone();
-    let mut pq = BinaryHeap::new();
-    pq.push( appraise(start[0], vgoals[0]) );
-    let goals = vec_to_set(vgoals.clone());
-    let mut visited = vec_to_set(start.clone());
-    let mut steps = HashMap::new();
+    pub steps: HashMap<V::Id, V::Id>,
+    pub g_score: HashMap<V::Id, f64>,
+    pub heuristic: Rc<dyn Fn(&V) -> f64>,
+    pub stats: SearchStats
 }
 
-impl<V: SearchNode> GraphSearch<Node2d> for GreedySearch<V> {
+impl<V: SearchNode> GreedySearch<V> {
 
-    fn step(&mut self) {
-        if self.result.is_over() {
-            return
-        }
-        if self.frontier.is_empty() {
-            self.result = SearchState::Failed("goal unreachable".to_string());
-            return
-        }
-        let current = self.frontier.remove(0);
-        debug!(target: "bfs", "visited: {:?}", self.visited);
-        debug!(target: "bfs", "current: {:?}", current.id());
-        debug!(target: "bfs", "steps  : {:?}", self.steps);
+    pub fn new(start: Vec<V>, heuristic: Rc<dyn Fn(&V) -> f64>) -> GreedySearch<V> {
+        let visited = start.iter().map(|node| node.id()).collect();
+        let g_score = start.iter().map(|node| (node.id(), 0.0)).collect();
+        let stats = SearchStats { generated: start.len(),
+                                  max_frontier: start.len(),
+                                  ..SearchStats::default() };
+        let frontier = start.into_iter()
+            .map(|node| Scored(heuristic(&node), node))
+            .collect();
+        GreedySearch { result: SearchState::NotStarted,
+                       frontier,
+                       visited,
+                       steps: HashMap::new(),
+                       g_score,
+                       heuristic,
+                       stats }
+    }
+
+    fn expand(&mut self) {
+        let current = match self.frontier.pop() {
+            None => {
+                self.result = SearchState::Failed(Error::GoalUnreachable);
+                return
+            },
+            Some (Scored(_, current)) => current
+        };
+        self.result = SearchState::InProgress;
+        debug!(target: "greedy", "visited: {:?}", self.visited);
+        debug!(target: "greedy", "current: {:?}", current.id());
+        debug!(target: "greedy", "steps  : {:?}", self.steps);
         if current.is_goal() {
-            debug!(target: "bfs", "goal found: {:?}", current.id());
+            debug!(target: "greedy", "goal found: {:?}", current.id());
             let path = build_path::<V>(&self.steps, current.id());
+            self.stats.path(&path, self.g_score[&current.id()]);
             self.result = SearchState::Finished(path);
             return
         }
-        let neighbours = current.neighbours();
-        let n_ids: Vec<V::Id> = neighbours.iter().map(|n| n.id()).collect();
-        debug!(target: "bfs", "allowed: {:?}", n_ids);
-        for next in neighbours.iter() {
+        self.stats.expanded += 1;
+        let g = self.g_score[&current.id()];
+        for next in current.neighbours() {
             if !self.visited.contains(&next.id()) {
-                self.frontier.push(next.clone());
                 self.visited.insert(next.id());
                 self.steps.insert(next.id(), current.id());
+                self.g_score.insert(next.id(), g + current.cost(&next));
+                self.frontier.push(Scored((self.heuristic)(&next), next));
+                self.stats.generated += 1;
             }
         }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for GreedySearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
     }
 
     fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
         let visited = self.visited.iter()
             .map(|pos| Node2d(pos.pos2d(), NodeState::Visited));
         let frontier = self.frontier.iter()
-            .map(|v| Node2d(v.id().pos2d(), NodeState::Frontier));
+            .map(|Scored(_, v)| Node2d(v.id().pos2d(), NodeState::Frontier));
         if let SearchState::Finished(ref path) = self.result {
             let path = path.iter().map(|pos| Node2d(pos.pos2d(), NodeState::Path));
             Box::new( visited.chain(frontier).chain(path) )
@@ -62,34 +107,31 @@ impl<V: SearchNode> GraphSearch<Node2d> for GreedySearch<V> {
 
 }
 
-fn greedy(start: Vec<Position>, vgoals: Vec<Position>, map: &Map) -> SearchResult {
-    loop {
-        let (_, pos) = match pq.pop() {
-            None => break,
-            Some (pos) => pos
-        };
-        debug!("visited: {:?}", visited);
-        debug!("current: {:?}", pos);
-        debug!("steps  : {:?}", steps);
-        if goals.contains(&pos) {
-            let path = reconstruct_path(pos, &steps);
-            return Ok (Search { start: start,
-                                goals: vgoals,
-                                paths: vec![path],
-                                visited: visited.into_iter().collect() })
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::{ GraphSearch, SearchState };
+    use crate::map;
+
+    #[test]
+    fn finds_contiguous_path_from_goal_to_start() {
+        let map = map::png::load("../test/fixtures/map2.png").unwrap();
+        let mut search = crate::greedy_search(map.clone());
+        while !search.result.is_over() {
+            search.step();
         }
-        let moves: Vec<(isize, Position)> = moves(pos, map.isize_dimensions()).iter()
-            .map(|new_pos| {
-                if !map[*new_pos].is_passable() { None }
-                else { Some (appraise(*new_pos, vgoals[0])) }
-            }).filter_map(|new_pos| new_pos).collect();
-        for &(cost, new_pos) in moves.iter() {
-            if !visited.contains(&new_pos) {
-                pq.push((cost, new_pos));
-                visited.insert(new_pos);
-                steps.insert(new_pos, pos);
-            }
+        match search.result {
+            SearchState::Finished(path) => {
+                assert_eq!(map.goals()[0], path[0]);
+                assert_eq!(map.start()[0], path[path.len() - 1]);
+                for step in path.windows(2) {
+                    let (dx, dy) = (step[0].0 as isize - step[1].0 as isize,
+                                    step[0].1 as isize - step[1].1 as isize);
+                    assert!(dx.abs() <= 1 && dy.abs() <= 1);
+                }
+            },
+            _ => panic!("path not found")
         }
     }
-    Err (Error::GoalUnreachable)
+
 }
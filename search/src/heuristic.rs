@@ -0,0 +1,128 @@
+use std::f64::consts::SQRT_2;
+
+/// Estimate of the remaining cost to a goal,
+/// given the absolute horizontal and vertical distance to it.
+pub trait Heuristic {
+    fn estimate(&self, delta: (usize, usize)) -> f64;
+}
+
+impl Heuristic for Box<dyn Heuristic> {
+    fn estimate(&self, delta: (usize, usize)) -> f64 {
+        (**self).estimate(delta)
+    }
+}
+
+/// Always zero, which turns A* into Dijkstra's algorithm.
+#[derive(Clone, Copy, Debug)]
+pub struct Zero;
+
+/// Sum of axis distances; admissible only on 4-connected grids.
+#[derive(Clone, Copy, Debug)]
+pub struct Manhattan;
+
+/// Larger of the axis distances; admissible when a diagonal costs as much as a straight move.
+#[derive(Clone, Copy, Debug)]
+pub struct Chebyshev;
+
+/// Exact cost on an open 8-connected grid with diagonal moves costing the square root of 2.
+#[derive(Clone, Copy, Debug)]
+pub struct Octile;
+
+/// Straight-line distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Euclidean;
+
+/// Straight-line distance rounded to the nearest integer.
+/// Kept for comparison: rounding up breaks consistency on 8-connected grids.
+#[derive(Clone, Copy, Debug)]
+pub struct RoundedEuclidean;
+
+/// Another heuristic scaled by a constant factor.
+/// Factors above 1 trade optimality of the found path for fewer expansions.
+#[derive(Clone, Copy, Debug)]
+pub struct Weighted<H: Heuristic>(pub f64, pub H);
+
+impl Heuristic for Zero {
+    fn estimate(&self, _delta: (usize, usize)) -> f64 { 0.0 }
+}
+
+impl Heuristic for Manhattan {
+    fn estimate(&self, (dx, dy): (usize, usize)) -> f64 { (dx + dy) as f64 }
+}
+
+impl Heuristic for Chebyshev {
+    fn estimate(&self, (dx, dy): (usize, usize)) -> f64 { dx.max(dy) as f64 }
+}
+
+impl Heuristic for Octile {
+    fn estimate(&self, (dx, dy): (usize, usize)) -> f64 {
+        let (short, long) = (dx.min(dy) as f64, dx.max(dy) as f64);
+        (long - short) + short * SQRT_2
+    }
+}
+
+impl Heuristic for Euclidean {
+    fn estimate(&self, (dx, dy): (usize, usize)) -> f64 {
+        (dx as f64).hypot(dy as f64)
+    }
+}
+
+impl Heuristic for RoundedEuclidean {
+    fn estimate(&self, delta: (usize, usize)) -> f64 {
+        Euclidean.estimate(delta).round()
+    }
+}
+
+impl<H: Heuristic> Heuristic for Weighted<H> {
+    fn estimate(&self, delta: (usize, usize)) -> f64 {
+        self.0 * self.1.estimate(delta)
+    }
+}
+
+pub fn by_name(name: &str) -> Option<Box<dyn Heuristic>> {
+    match name {
+        "zero"              => Some (Box::new(Zero)),
+        "manhattan"         => Some (Box::new(Manhattan)),
+        "chebyshev"         => Some (Box::new(Chebyshev)),
+        "octile"            => Some (Box::new(Octile)),
+        "euclidean"         => Some (Box::new(Euclidean)),
+        "rounded-euclidean" => Some (Box::new(RoundedEuclidean)),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Find a move between neighbouring cells which drops the estimate by more than it costs.
+    fn inconsistency(heuristic: &dyn Heuristic) -> Option<((usize, usize), (usize, usize))> {
+        for x in 1 .. 12 {
+            for y in 1 .. 12 {
+                for &(nx, ny) in &[(x-1, y), (x, y-1), (x-1, y-1), (x+1, y-1)] {
+                    let cost = if nx != x && ny != y { SQRT_2 } else { 1.0 };
+                    let drop = heuristic.estimate((x, y)) - heuristic.estimate((nx, ny));
+                    if drop > cost + 1e-9 {
+                        return Some (((x, y), (nx, ny)))
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn consistent_heuristics_on_8_connected_grid() {
+        assert_eq!(None, inconsistency(&Zero));
+        assert_eq!(None, inconsistency(&Chebyshev));
+        assert_eq!(None, inconsistency(&Octile));
+        assert_eq!(None, inconsistency(&Euclidean));
+    }
+
+    #[test]
+    fn rounded_euclidean_is_inconsistent_on_8_connected_grid() {
+        assert_eq!(Some (((2, 2), (1, 1))), inconsistency(&RoundedEuclidean));
+    }
+
+}
@@ -0,0 +1,199 @@
+use crate::Error;
+use crate::graph::*;
+use crate::stats::SearchStats;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// A node on the current path together with its neighbours yet to be tried.
+#[derive(Clone)]
+pub struct Frame<V> {
+    pub node: V,
+    pub g: f64,
+    /// Untried neighbours, the next one last.
+    pub pending: Vec<V>
+}
+
+/// Iterative deepening A*: depth-first searches bounded by an f-cost threshold,
+/// raised to the lowest f-cost that exceeded it after each unsuccessful iteration.
+///
+/// Only the current path is kept in memory, at the price of expanding nodes repeatedly.
+/// Each iteration starts over from scratch, which takes a step of its own.
+#[derive(Clone)]
+pub struct IDAStarSearch<V: SearchNode> {
+    pub result: SearchState<V>,
+    pub start: Vec<V>,
+    /// Start nodes yet to be searched from in the current iteration, the next one last.
+    pub roots: Vec<V>,
+    pub path: Vec<Frame<V>>,
+    pub threshold: f64,
+    /// Lowest f-cost above the threshold met in the current iteration.
+    pub next_threshold: f64,
+    pub heuristic: Rc<dyn Fn(&V) -> f64>,
+    pub stats: SearchStats
+}
+
+impl<V: SearchNode> IDAStarSearch<V> {
+
+    pub fn new(start: Vec<V>, heuristic: Rc<dyn Fn(&V) -> f64>) -> IDAStarSearch<V> {
+        let threshold = start.iter().map(|node| heuristic(node)).fold(f64::INFINITY, f64::min);
+        let stats = SearchStats { generated: start.len(),
+                                  max_frontier: start.len(),
+                                  thresholds: vec![threshold],
+                                  ..SearchStats::default() };
+        IDAStarSearch { result: SearchState::NotStarted,
+                        roots: start.iter().rev().cloned().collect(),
+                        start,
+                        path: vec![],
+                        threshold,
+                        next_threshold: f64::INFINITY,
+                        heuristic,
+                        stats }
+    }
+
+    /// Next node to try and the cost of reaching it, backtracking from exhausted nodes.
+    fn next_candidate(&mut self) -> Option<(V, f64)> {
+        while let Some (frame) = self.path.last_mut() {
+            match frame.pending.pop() {
+                Some (next) => {
+                    let g = frame.g + frame.node.cost(&next);
+                    return Some ((next, g))
+                },
+                None => { self.path.pop(); }
+            }
+        }
+        self.roots.pop().map(|root| (root, 0.0))
+    }
+
+    fn deepen(&mut self) {
+        if self.next_threshold == f64::INFINITY {
+            self.result = SearchState::Failed(Error::GoalUnreachable);
+            return
+        }
+        debug!(target: "idastar", "raising threshold to {}", self.next_threshold);
+        self.threshold = self.next_threshold;
+        self.next_threshold = f64::INFINITY;
+        self.roots = self.start.iter().rev().cloned().collect();
+        self.stats.generated += self.start.len();
+        self.stats.thresholds.push(self.threshold);
+    }
+
+    fn expand(&mut self) {
+        self.result = SearchState::InProgress;
+        let (current, g) = loop {
+            let (node, g) = match self.next_candidate() {
+                None => return self.deepen(),
+                Some (candidate) => candidate
+            };
+            let f = g + (self.heuristic)(&node);
+            // Tolerate rounding errors of summing up diagonal moves in different orders.
+            if f > self.threshold + 1e-9 {
+                self.next_threshold = self.next_threshold.min(f);
+            } else if !self.path.iter().any(|frame| frame.node.id() == node.id()) {
+                break (node, g)
+            }
+        };
+        debug!(target: "idastar", "current: {:?}", current.id());
+        if current.is_goal() {
+            debug!(target: "idastar", "goal found: {:?}", current.id());
+            let path: Vec<V::Id> = self.path.iter().map(|frame| frame.node.id())
+                .chain(Some (current.id()))
+                .rev()
+                .collect();
+            self.stats.path(&path, g);
+            self.result = SearchState::Finished(path);
+            return
+        }
+        self.stats.expanded += 1;
+        let mut pending = current.neighbours();
+        pending.reverse();
+        self.stats.generated += pending.len();
+        self.path.push(Frame { node: current, g, pending });
+        let frontier = self.path.iter().map(|frame| frame.pending.len()).sum();
+        self.stats.frontier_size(frontier);
+    }
+
+}
+
+impl<V: SearchNode> GraphSearch<Node2d> for IDAStarSearch<V> {
+
+    type Node = V;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<V> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.path.iter()
+            .map(|frame| Node2d(frame.node.id().pos2d(), NodeState::Visited));
+        let frontier = self.path.iter()
+            .flat_map(|frame| frame.pending.iter())
+            .map(|v| Node2d(v.id().pos2d(), NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|pos| Node2d(pos.pos2d(), NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::IDAStarSearch;
+    use crate::graph::{ GraphSearch, SearchNode };
+    use crate::map;
+    use std::rc::Rc;
+
+    #[test]
+    fn finds_paths_as_cheap_as_astar() {
+        let map = map::png::load("../test/fixtures/map2.png").unwrap();
+        let mut astar = crate::astar_search(map.clone());
+        astar.run(None).unwrap();
+        let mut idastar = crate::idastar_search(map);
+        idastar.run(None).unwrap();
+        assert!((astar.stats.path_cost - idastar.stats.path_cost).abs() < 1e-9);
+        let thresholds = &idastar.stats.thresholds;
+        assert!(thresholds.windows(2).all(|t| t[0] < t[1]));
+        assert!(thresholds[thresholds.len() - 1] >= idastar.stats.path_cost - 1e-9);
+    }
+
+    /// Numbers reachable by incrementing or doubling.
+    #[derive(Clone, PartialEq, Eq, Hash)]
+    struct Number(usize);
+
+    impl SearchNode for Number {
+        type Id = (usize, usize);
+        fn id(&self) -> (usize, usize) { (self.0, 0) }
+        fn is_goal(&self) -> bool { self.0 == 24 }
+        fn neighbours(&self) -> Vec<Number> {
+            vec![Number(self.0 + 1), Number(self.0 * 2)].into_iter()
+                .filter(|n| n.0 <= 24)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn searches_any_graph() {
+        let mut search = IDAStarSearch::new(vec![Number(1)], Rc::new(|_: &Number| 0.0));
+        let path = search.run(None).unwrap();
+        // 1 -> 2 -> 3 -> 6 -> 12 -> 24
+        assert_eq!(vec![(24,0), (12,0), (6,0), (3,0), (2,0), (1,0)], path);
+        assert_eq!(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], search.stats.thresholds);
+    }
+
+}
@@ -0,0 +1,523 @@
+//! Incremental searches which repair their solution after fields of the map change,
+//! rather than searching again from scratch: Lifelong Planning A* and D* Lite.
+
+use crate::graph::*;
+use crate::heuristic::{ Heuristic, Octile };
+use crate::map::{ Field, Map, Position };
+use crate::stats::SearchStats;
+use crate::{ allowed_moves, displacements, step_cost, Error, MapField };
+use std::cmp::Ordering;
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::time::Instant;
+
+/// Priority of a field: the estimated length of paths through it, then the length of the path to it.
+type Key = (f64, f64);
+
+/// Whether a key comes before another. Estimates often equal the remaining cost exactly,
+/// so first components differing only by rounding errors count as equal.
+fn precedes(a: Key, b: Key) -> bool {
+    if (a.0 - b.0).abs() <= 1e-9 || a.0 == b.0 { a.1 < b.1 } else { a.0 < b.0 }
+}
+
+/// Queue entry; the queue pops the lowest key first.
+#[derive(Clone)]
+struct Keyed(Key, Position);
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool { self.0 == other.0 }
+}
+
+impl Eq for Keyed {}
+
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some (self.cmp(other)) }
+}
+
+impl Ord for Keyed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Lifelong Planning A* searching from the start, or D* Lite searching from the goals.
+///
+/// Each field has a g-score, the length of the path to it found so far,
+/// and a right-hand side score computed from its neighbours' g-scores.
+/// Only fields where the two differ are queued, so after a map update
+/// just the fields whose distances changed are expanded again.
+///
+/// D* Lite searches backwards so that the start can move along the path
+/// while everything known about distances to the goals stays valid.
+#[derive(Clone)]
+pub struct IncrementalSearch {
+    pub result: SearchState<MapField>,
+    pub map: Map,
+    /// Whether the search runs from the goals to the start, as D* Lite does.
+    pub backward: bool,
+    /// Fields the search grows from: the start for LPA*, the goals for D* Lite.
+    pub roots: Vec<Position>,
+    /// Fields the search looks for paths to.
+    pub targets: Vec<Position>,
+    pub g_score: HashMap<Position, f64>,
+    pub rhs: HashMap<Position, f64>,
+    queue: BinaryHeap<Keyed>,
+    /// Current keys of queued fields; queue entries with other keys are stale.
+    queued: HashMap<Position, Key>,
+    /// Sum of the distances the start moved, added to new keys to keep old ones valid.
+    pub key_modifier: f64,
+    pub visited: HashSet<Position>,
+    /// Fields expanded after each map update, until the path was repaired.
+    pub repairs: Vec<usize>,
+    /// Fields blocked by toggling, with what they were before.
+    blocked: HashMap<Position, Field>,
+    pub stats: SearchStats
+}
+
+impl IncrementalSearch {
+
+    pub fn lpa_star(map: Map) -> IncrementalSearch {
+        IncrementalSearch::new(map, false)
+    }
+
+    pub fn dstar_lite(map: Map) -> IncrementalSearch {
+        IncrementalSearch::new(map, true)
+    }
+
+    fn new(map: Map, backward: bool) -> IncrementalSearch {
+        let (roots, targets) = if backward { (map.goals(), map.start()) }
+                               else { (map.start(), map.goals()) };
+        let mut search = IncrementalSearch { result: SearchState::NotStarted,
+                                             map,
+                                             backward,
+                                             roots: roots.clone(),
+                                             targets,
+                                             g_score: HashMap::new(),
+                                             rhs: HashMap::new(),
+                                             queue: BinaryHeap::new(),
+                                             queued: HashMap::new(),
+                                             key_modifier: 0.0,
+                                             visited: HashSet::new(),
+                                             repairs: vec![],
+                                             blocked: HashMap::new(),
+                                             stats: SearchStats::default() };
+        for root in roots {
+            search.rhs.insert(root, 0.0);
+            search.enqueue(root);
+        }
+        search.stats.generated = search.queue.len();
+        search.stats.max_frontier = search.queue.len();
+        search
+    }
+
+    fn g(&self, pos: Position) -> f64 {
+        self.g_score.get(&pos).cloned().unwrap_or(f64::INFINITY)
+    }
+
+    fn rhs(&self, pos: Position) -> f64 {
+        self.rhs.get(&pos).cloned().unwrap_or(f64::INFINITY)
+    }
+
+    fn estimate(&self, pos: Position) -> f64 {
+        self.targets.iter()
+            .map(|&target| Octile.estimate(self.map.delta(pos, target)))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    fn key(&self, pos: Position) -> Key {
+        let g = self.g(pos).min(self.rhs(pos));
+        (g + self.estimate(pos) + self.key_modifier, g)
+    }
+
+    fn enqueue(&mut self, pos: Position) {
+        let key = self.key(pos);
+        self.queued.insert(pos, key);
+        self.queue.push(Keyed(key, pos));
+    }
+
+    /// Fields next to `pos`, whether passable or not.
+    fn neighbours(&self, pos: Position) -> Vec<Position> {
+        displacements(pos, self.map.isize_dimensions(), self.map.rules.shape)
+            .into_iter()
+            .map(|(_, moved)| moved)
+            .collect()
+    }
+
+    /// Cost of moving from one field to another; infinite if the move isn't allowed.
+    fn cost(&self, from: Position, to: Position) -> f64 {
+        if self.map[from].is_passable() && allowed_moves(&self.map, from).contains(&to) {
+            step_cost(&self.map, from, to)
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// Cost of the move between a field and a neighbour closer to the roots, in the direction of travel.
+    fn link(&self, pos: Position, towards_roots: Position) -> f64 {
+        if self.backward { self.cost(pos, towards_roots) } else { self.cost(towards_roots, pos) }
+    }
+
+    /// Recompute the right-hand side score of a field and queue it if it's inconsistent.
+    fn update(&mut self, pos: Position) {
+        if !self.roots.contains(&pos) {
+            let rhs = self.neighbours(pos).into_iter()
+                .map(|n| self.g(n) + self.link(pos, n))
+                .fold(f64::INFINITY, f64::min);
+            self.rhs.insert(pos, rhs);
+        }
+        if self.g(pos) != self.rhs(pos) {
+            self.enqueue(pos);
+            self.stats.generated += 1;
+        } else {
+            self.queued.remove(&pos);
+        }
+    }
+
+    /// Lowest valid queue entry, dropping stale ones.
+    fn peek(&mut self) -> Option<Keyed> {
+        while let Some (entry) = self.queue.peek() {
+            if self.queued.get(&entry.1) == Some (&entry.0) {
+                return Some (entry.clone())
+            }
+            self.queue.pop();
+        }
+        None
+    }
+
+    /// Change a field, e.g. once it's found to be blocked, and make the search repair its path.
+    /// The start and goals stay where they were.
+    pub fn update_field(&mut self, pos: Position, field: Field) -> Result<(), Error> {
+        self.check_bounds(pos)?;
+        if self.map[pos] == field {
+            return Ok (())
+        }
+        debug!(target: "incremental", "{:?} changed to {:?}", pos, field);
+        self.map[pos] = field;
+        self.refresh(pos);
+        self.repairs.push(0);
+        if let SearchState::NotStarted = self.result {} else {
+            self.result = SearchState::InProgress;
+        }
+        Ok (())
+    }
+
+    /// Block a passable field or clear an impassable one, returning what the field is now;
+    /// start and goal fields are left alone. Clearing a field restores the weight it had
+    /// before it was blocked.
+    pub fn toggle_field(&mut self, pos: Position) -> Result<Field, Error> {
+        self.check_bounds(pos)?;
+        match self.map[pos] {
+            Field::Start | Field::Goal => (),
+            Field::Impassable => {
+                let cleared = self.blocked.remove(&pos).unwrap_or(Field::Passable);
+                self.update_field(pos, cleared)?
+            },
+            field => {
+                self.blocked.insert(pos, field);
+                self.update_field(pos, Field::Impassable)?
+            }
+        }
+        Ok (self.map[pos])
+    }
+
+    /// Move the start, e.g. as an agent follows the path.
+    ///
+    /// D* Lite keeps what it found out; LPA* searches from the start, so it has to start over.
+    pub fn move_start(&mut self, pos: Position) -> Result<(), Error> {
+        let mut map = self.map.clone();
+        map.set_start(&[pos])?;
+        if !self.backward {
+            *self = IncrementalSearch::new(map, false);
+            return Ok (())
+        }
+        if let Some (&last) = self.targets.first() {
+            self.key_modifier += Octile.estimate(self.map.delta(last, pos));
+        }
+        // The old start turns passable and the new one may have been blocked or weighted,
+        // so both change costs like any other updated field.
+        let changed: Vec<_> = self.targets.iter().cloned().chain(Some (pos)).collect();
+        self.map = map;
+        for changed in changed {
+            self.refresh(changed);
+        }
+        self.targets = vec![pos];
+        self.repairs.push(0);
+        self.result = SearchState::InProgress;
+        Ok (())
+    }
+
+    fn check_bounds(&self, (x,y): Position) -> Result<(), Error> {
+        if x >= self.map.width || y >= self.map.height {
+            return Err (Error::OutOfBounds((x,y)))
+        }
+        Ok (())
+    }
+
+    /// Recompute the scores of a changed field and its neighbours.
+    fn refresh(&mut self, pos: Position) {
+        self.update(pos);
+        for n in self.neighbours(pos) {
+            self.update(n);
+        }
+    }
+
+    /// Follow the lowest scores from a target back to the roots.
+    fn finish(&mut self, target: Position) {
+        let cost = self.g(target);
+        if cost == f64::INFINITY {
+            self.result = SearchState::Failed(Error::GoalUnreachable);
+            return
+        }
+        let mut path = vec![target];
+        let mut current = target;
+        while !self.roots.contains(&current) && path.len() <= self.map.fields.len() {
+            current = self.neighbours(current).into_iter()
+                .map(|n| (self.g(n) + self.link(current, n), n))
+                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                .map(|(_, n)| n)
+                .unwrap_or(current);
+            path.push(current);
+        }
+        if self.backward {
+            path.reverse();
+        }
+        debug!(target: "incremental", "path found: {} fields", path.len());
+        self.stats.path(&path, cost);
+        self.result = SearchState::Finished(path);
+    }
+
+    fn expand(&mut self) {
+        self.result = SearchState::InProgress;
+        let best_target = self.targets.iter()
+            .map(|&t| (self.key(t), t))
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let (target_key, target) = match best_target {
+            None => {
+                self.result = SearchState::Failed(Error::GoalUnreachable);
+                return
+            },
+            Some (best) => best
+        };
+        let top = self.peek();
+        let settled = top.as_ref().is_none_or(|Keyed(key, _)| !precedes(*key, target_key));
+        if settled && self.g(target) == self.rhs(target) {
+            return self.finish(target)
+        }
+        let Keyed(old_key, current) = match top {
+            Some (entry) => entry,
+            None => return self.finish(target)
+        };
+        self.queue.pop();
+        self.queued.remove(&current);
+        let new_key = self.key(current);
+        if precedes(old_key, new_key) {
+            // The start moved since the field was queued.
+            self.enqueue(current);
+            return
+        }
+        debug!(target: "incremental", "current: {:?}", current);
+        self.stats.expanded += 1;
+        if !self.visited.insert(current) {
+            self.stats.reopened += 1;
+        }
+        if let Some (repaired) = self.repairs.last_mut() {
+            *repaired += 1;
+        }
+        if self.g(current) > self.rhs(current) {
+            self.g_score.insert(current, self.rhs(current));
+        } else {
+            self.g_score.insert(current, f64::INFINITY);
+            self.update(current);
+        }
+        for n in self.neighbours(current) {
+            self.update(n);
+        }
+        self.stats.frontier_size(self.queued.len());
+    }
+
+}
+
+impl GraphSearch<Node2d> for IncrementalSearch {
+
+    type Node = MapField;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<MapField> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.visited.iter()
+            .filter(move |pos| self.g(**pos) < f64::INFINITY)
+            .map(|&pos| Node2d(pos, NodeState::Visited));
+        let frontier = self.queued.keys()
+            .map(|&pos| Node2d(pos, NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|&pos| Node2d(pos, NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::GraphSearch;
+    use crate::map::{ self, ascii, generate, Connectivity, Field, Map };
+    use crate::Error;
+
+    fn cost_from_scratch(map: &Map) -> Option<f64> {
+        let mut astar = crate::astar_search(map.clone());
+        astar.run(None).ok().map(|_| astar.stats.path_cost)
+    }
+
+    #[test]
+    fn repairs_paths_as_cheap_as_new_searches() {
+        let mut map = map::png::load("../test/fixtures/map2.png").unwrap();
+        let mut searches = [crate::lpa_star(map.clone()), crate::dstar_lite(map.clone())];
+        let blocked: Vec<_> = map.positions()
+            .filter(|&pos| map[pos] == Field::Passable)
+            .step_by(7)
+            .take(6)
+            .collect();
+        for search in searches.iter_mut() {
+            search.run(None).unwrap();
+            assert!((search.stats.path_cost - cost_from_scratch(&map).unwrap()).abs() < 1e-9);
+        }
+        for &pos in &blocked {
+            map[pos] = Field::Impassable;
+            for search in searches.iter_mut() {
+                search.update_field(pos, Field::Impassable).unwrap();
+                let found = search.run(None).map(|_| search.stats.path_cost);
+                match (found, cost_from_scratch(&map)) {
+                    (Ok (cost), Some (expected)) => assert!((cost - expected).abs() < 1e-9,
+                                                            "{} instead of {}", cost, expected),
+                    (Err (Error::GoalUnreachable), None) => (),
+                    (found, expected) => panic!("{:?} instead of {:?}", found.ok(), expected)
+                }
+                let path = crate::path::normalise(&map, search.run(None).unwrap_or_default());
+                if !path.is_empty() {
+                    map.validate_path(&path, &map.rules).unwrap();
+                }
+            }
+        }
+        for search in searches.iter() {
+            assert_eq!(blocked.len(), search.repairs.len());
+        }
+    }
+
+    #[test]
+    fn repairs_weights_under_strict_diagonal_rules() {
+        let changes = [Field::Impassable, Field::Weighted(3), Field::Passable, Field::Weighted(7)];
+        for seed in 0 .. 20 {
+            for &connectivity in &[Connectivity::EightNoSqueezing, Connectivity::EightNoCornerCutting] {
+                let mut map = generate::obstacles(24, 18, 0.3, seed);
+                map.rules.connectivity = connectivity;
+                let mut searches = [crate::lpa_star(map.clone()), crate::dstar_lite(map.clone())];
+                let fields: Vec<_> = map.positions()
+                    .filter(|&pos| map[pos] != Field::Start && map[pos] != Field::Goal)
+                    .collect();
+                for update in 0 .. 24 {
+                    let pos = fields[(update * update * 131 + update * 97 + seed as usize * 31) % fields.len()];
+                    let field = changes[update % changes.len()];
+                    map[pos] = field;
+                    let expected = cost_from_scratch(&map);
+                    for search in searches.iter_mut() {
+                        search.update_field(pos, field).unwrap();
+                        let found = search.run(None).ok().map(|_| search.stats.path_cost);
+                        match (found, expected) {
+                            (Some (cost), Some (expected)) =>
+                                assert!((cost - expected).abs() < 1e-9,
+                                        "seed {} {:?} update {}: {} instead of {}",
+                                        seed, connectivity, update, cost, expected),
+                            (None, None) => (),
+                            (found, expected) => panic!("seed {} {:?} update {}: {:?} instead of {:?}",
+                                                        seed, connectivity, update, found, expected)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn repairs_touch_few_fields() {
+        let map = ascii::parse("S.........\n..........\n..........\n.........G\n",
+                               &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::dstar_lite(map);
+        search.run(None).unwrap();
+        let initial = search.stats.expanded;
+        search.toggle_field((9,0)).unwrap();
+        search.run(None).unwrap();
+        assert_eq!(vec![search.stats.expanded - initial], search.repairs);
+        assert!(search.repairs[0] < initial);
+        search.toggle_field((5,0)).unwrap();
+        search.toggle_field((5,1)).unwrap();
+        search.toggle_field((5,2)).unwrap();
+        search.toggle_field((5,3)).unwrap();
+        match search.run(None) {
+            Err (Error::GoalUnreachable) => (),
+            _ => panic!("expected GoalUnreachable")
+        }
+        search.toggle_field((5,3)).unwrap();
+        assert_eq!(10, search.run(None).unwrap().len());
+    }
+
+    #[test]
+    fn start_moves_along_the_path() {
+        let map = ascii::parse("S...\n###.\n...G\n", &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::dstar_lite(map);
+        let path = search.run(None).unwrap();
+        let next = path[path.len() - 2];
+        search.move_start(next).unwrap();
+        let rest = search.run(None).unwrap();
+        assert_eq!(&path[.. path.len() - 1], &rest[..]);
+        assert_eq!(Some (&next), rest.last());
+    }
+
+    #[test]
+    fn rejects_fields_out_of_bounds() {
+        let map = ascii::parse("S.G\n", &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::lpa_star(map);
+        match search.toggle_field((3,0)) {
+            Err (Error::OutOfBounds((3,0))) => (),
+            _ => panic!("expected OutOfBounds")
+        }
+        match search.update_field((0,1), Field::Impassable) {
+            Err (Error::OutOfBounds((0,1))) => (),
+            _ => panic!("expected OutOfBounds")
+        }
+    }
+
+    #[test]
+    fn toggling_keeps_weights() {
+        let map = ascii::parse("S5G\n", &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::dstar_lite(map);
+        assert_eq!(Field::Impassable, search.toggle_field((1,0)).unwrap());
+        assert_eq!(Field::Weighted(5), search.toggle_field((1,0)).unwrap());
+    }
+
+    #[test]
+    fn start_moves_onto_blocked_fields() {
+        let map = ascii::parse("S.G#\n", &ascii::Glyphs::default()).unwrap();
+        let mut search = crate::dstar_lite(map);
+        search.run(None).unwrap();
+        search.move_start((3,0)).unwrap();
+        assert_eq!(vec![(2,0), (3,0)], search.run(None).unwrap());
+    }
+
+}
@@ -0,0 +1,487 @@
+//! Jump point search: A* for uniform-cost grids with diagonal moves,
+//! which skips over the many equally long paths through open areas
+//! and only stops at jump points, where obstacles force a path to turn.
+//!
+//! JPS+ additionally precomputes how far to jump from every field in every direction.
+
+use crate::graph::*;
+use crate::heuristic::{ Heuristic, Octile };
+use crate::map::{ Connectivity, Field, Map, Position, WorldShape };
+use crate::stats::SearchStats;
+use crate::{ Error, MapField };
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Moves in the order of the bits of neighbourhood patterns and move masks.
+const DIRECTIONS: [(isize, isize); 8] = [( 0, -1), ( 1, -1), ( 1,  0), ( 1,  1),
+                                         ( 0,  1), (-1,  1), (-1,  0), (-1, -1)];
+
+/// Index into `DIRECTIONS` standing for the moves from a start node, which has no parent.
+const NO_PARENT: usize = 8;
+
+fn direction_index(d: (isize, isize)) -> usize {
+    DIRECTIONS.iter().position(|&other| other == d)
+        .unwrap_or_else(|| unreachable!("{:?} is not a move", d))
+}
+
+fn is_diagonal(d: usize) -> bool {
+    d % 2 == 1
+}
+
+fn move_cost(d: usize) -> f64 {
+    if is_diagonal(d) { std::f64::consts::SQRT_2 } else { 1.0 }
+}
+
+/// Moves which keep going in the general direction of move `d`.
+fn natural(d: usize) -> u8 {
+    let (dx, dy) = DIRECTIONS[d];
+    if is_diagonal(d) {
+        1 << d | 1 << direction_index((dx, 0)) | 1 << direction_index((0, dy))
+    } else {
+        1 << d
+    }
+}
+
+fn offset((x, y): Position, d: usize, times: usize) -> Position {
+    let (dx, dy) = DIRECTIONS[d];
+    ((x as isize + dx * times as isize) as usize, (y as isize + dy * times as isize) as usize)
+}
+
+/// Check that jump point search finds the shortest paths on the map.
+pub fn check_map(map: &Map) -> Result<(), Error> {
+    let unsupported = |reason: &str| Err (Error::UnsupportedMap(reason.to_string()));
+    if map.rules.shape != WorldShape::Bounded {
+        return unsupported("jump point search needs a bounded map")
+    }
+    if map.rules.connectivity == Connectivity::Four {
+        return unsupported("jump point search needs diagonal moves")
+    }
+    if map.fields.iter().any(|f| f.is_passable() && f.cost() != 1.0) {
+        return unsupported("jump point search needs all passable fields to cost the same")
+    }
+    Ok (())
+}
+
+/// Passability of the 8 fields around `pos`, one bit per direction.
+fn neighbourhood(map: &Map, (x, y): Position) -> u8 {
+    let mut pattern = 0;
+    for (i, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        if nx >= 0 && ny >= 0 && (nx as usize) < map.width && (ny as usize) < map.height
+            && map[(nx as usize, ny as usize)].is_passable() {
+            pattern |= 1 << i
+        }
+    }
+    pattern
+}
+
+/// Moves worth taking from a node, depending only on the passability of its surroundings
+/// and the direction it was entered from, so they're worked out once for every combination.
+#[derive(Clone)]
+struct Pruning {
+    /// Masks of moves, indexed by `256 * incoming direction + neighbourhood pattern`.
+    successors: Vec<u8>
+}
+
+impl Pruning {
+
+    fn new(connectivity: Connectivity) -> Pruning {
+        let successors = (0 ..= NO_PARENT)
+            .flat_map(|incoming| (0 ..= u8::MAX).map(move |pattern| prune(connectivity, pattern, incoming)))
+            .collect();
+        Pruning { successors }
+    }
+
+    fn successors(&self, pattern: u8, incoming: usize) -> u8 {
+        self.successors[256 * incoming + pattern as usize]
+    }
+
+    /// Whether any move that needs to be taken from the node doesn't keep its general direction.
+    fn is_forced(&self, pattern: u8, incoming: usize) -> bool {
+        self.successors(pattern, incoming) & !natural(incoming) != 0
+    }
+
+}
+
+/// Moves from the centre of a neighbourhood which no path from the parent avoiding the centre
+/// makes redundant: none shorter for a straight incoming move, none as short for a diagonal one.
+fn prune(connectivity: Connectivity, pattern: u8, incoming: usize) -> u8 {
+    let free = |(x, y): (isize, isize)| (x, y) == (0, 0)
+        || x.abs() <= 1 && y.abs() <= 1 && pattern & 1 << direction_index((x, y)) != 0;
+    let allowed = |(x, y): (isize, isize), d: usize| {
+        let (dx, dy) = DIRECTIONS[d];
+        free((x + dx, y + dy)) && connectivity.allows((dx, dy), free((x + dx, y)), free((x, y + dy)))
+    };
+    let moves = (0 .. 8).filter(|&d| allowed((0, 0), d)).fold(0, |mask, d| mask | 1 << d);
+    if incoming == NO_PARENT {
+        return moves
+    }
+    let (px, py) = DIRECTIONS[incoming];
+    let parent = direction_index((-px, -py));
+    // Shortest paths within the neighbourhood from the parent, not passing the centre.
+    let mut distance = [f64::INFINITY; 8];
+    distance[parent] = 0.0;
+    for _ in 0 .. 8 {
+        for (from, &(x, y)) in DIRECTIONS.iter().enumerate() {
+            for (d, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let to = (x + dx, y + dy);
+                if to != (0, 0) && to.0.abs() <= 1 && to.1.abs() <= 1 && allowed((x, y), d) {
+                    let to = direction_index(to);
+                    distance[to] = distance[to].min(distance[from] + move_cost(d));
+                }
+            }
+        }
+    }
+    (0 .. 8)
+        .filter(|&d| moves & 1 << d != 0)
+        .filter(|&d| {
+            let through_centre = move_cost(incoming) + move_cost(d);
+            if is_diagonal(incoming) {
+                distance[d] >= through_centre - 1e-9
+            } else {
+                distance[d] > through_centre + 1e-9
+            }
+        })
+        .fold(0, |mask, d| mask | 1 << d)
+}
+
+/// Distances to jump from every field of a map in each direction, for JPS+.
+///
+/// A positive distance leads to a jump point,
+/// otherwise its absolute value is how far it's possible to move before hitting an obstacle.
+#[derive(Clone)]
+pub struct JumpTable {
+    pub width: usize,
+    pub height: usize,
+    pub distances: Vec<[i32; 8]>
+}
+
+impl JumpTable {
+
+    pub fn new(map: &Map) -> Result<JumpTable, Error> {
+        check_map(map)?;
+        let pruning = Pruning::new(map.rules.connectivity);
+        let (width, height) = (map.width, map.height);
+        let patterns: Vec<u8> = map.positions().map(|pos| neighbourhood(map, pos)).collect();
+        let mut distances = vec![[0; 8]; width * height];
+        // Diagonal jumps stop where straight ones find jump points, so straight ones go first.
+        for &d in &[0, 2, 4, 6, 1, 3, 5, 7] {
+            let (dx, dy) = DIRECTIONS[d];
+            // Fields are visited so that the neighbour in the direction of the jump comes first.
+            let xs: Vec<usize> = if dx > 0 { (0 .. width).rev().collect() } else { (0 .. width).collect() };
+            let ys: Vec<usize> = if dy > 0 { (0 .. height).rev().collect() } else { (0 .. height).collect() };
+            for &y in ys.iter() {
+                for &x in xs.iter() {
+                    let here = y * width + x;
+                    if !map[(x, y)].is_passable() || pruning.successors(patterns[here], NO_PARENT) & 1 << d == 0 {
+                        continue
+                    }
+                    let (nx, ny) = offset((x, y), d, 1);
+                    let next = ny * width + nx;
+                    let jump_point = pruning.is_forced(patterns[next], d)
+                        || is_diagonal(d) && [direction_index((dx, 0)), direction_index((0, dy))].iter()
+                               .any(|&straight| distances[next][straight] > 0);
+                    let further = distances[next][d];
+                    distances[here][d] = if jump_point { 1 }
+                                         else if further > 0 { further + 1 }
+                                         else { further - 1 };
+                }
+            }
+        }
+        Ok (JumpTable { width, height, distances })
+    }
+
+    pub fn distance(&self, (x, y): Position, direction: (isize, isize)) -> i32 {
+        self.distances[y * self.width + x][direction_index(direction)]
+    }
+
+}
+
+/// Step-wise jump point search; it uses a jump table if it has one, i.e. it's JPS+.
+///
+/// Only jump points are visited and put on the frontier;
+/// the found path is filled in between them to consist of single moves like any other.
+#[derive(Clone)]
+pub struct JumpPointSearch {
+    pub result: SearchState<MapField>,
+    pub map: Rc<Map>,
+    pub table: Option<Rc<JumpTable>>,
+    pruning: Pruning,
+    pub goals: Vec<Position>,
+    pub frontier: BinaryHeap<Scored<Position>>,
+    pub visited: HashSet<Position>,
+    pub steps: HashMap<Position, Position>,
+    /// Direction of the last move into each jump point, which decides the moves worth taking from it.
+    pub directions: HashMap<Position, (isize, isize)>,
+    pub g_score: HashMap<Position, f64>,
+    pub stats: SearchStats
+}
+
+impl JumpPointSearch {
+
+    /// Search the map, which should pass `check_map`;
+    /// a jump table has to be made from the same map.
+    pub fn new(map: Rc<Map>, table: Option<Rc<JumpTable>>) -> JumpPointSearch {
+        let goals = map.goals();
+        let mut search = JumpPointSearch { result: SearchState::NotStarted,
+                                           pruning: Pruning::new(map.rules.connectivity),
+                                           table,
+                                           goals,
+                                           frontier: BinaryHeap::new(),
+                                           visited: HashSet::new(),
+                                           steps: HashMap::new(),
+                                           directions: HashMap::new(),
+                                           g_score: HashMap::new(),
+                                           stats: SearchStats::default(),
+                                           map };
+        for pos in search.map.start() {
+            search.g_score.insert(pos, 0.0);
+            search.frontier.push(Scored(search.estimate(pos), pos));
+        }
+        search.stats.generated = search.frontier.len();
+        search.stats.max_frontier = search.frontier.len();
+        search
+    }
+
+    fn estimate(&self, pos: Position) -> f64 {
+        self.goals.iter()
+            .map(|goal| Octile.estimate(self.map.delta(pos, *goal)))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Move from `from` in direction `d` until reaching a jump point, if there's any.
+    fn jump(&self, from: Position, d: usize) -> Option<Position> {
+        let mut current = from;
+        let mut pattern = neighbourhood(&self.map, current);
+        loop {
+            if self.pruning.successors(pattern, NO_PARENT) & 1 << d == 0 {
+                return None
+            }
+            current = offset(current, d, 1);
+            if self.map[current] == Field::Goal {
+                return Some (current)
+            }
+            pattern = neighbourhood(&self.map, current);
+            if self.pruning.is_forced(pattern, d) {
+                return Some (current)
+            }
+            if is_diagonal(d) {
+                let (dx, dy) = DIRECTIONS[d];
+                let straight = [direction_index((dx, 0)), direction_index((0, dy))];
+                if straight.iter().any(|&s| self.jump(current, s).is_some()) {
+                    return Some (current)
+                }
+            }
+        }
+    }
+
+    /// Like `jump`, but looking the distance up in the jump table.
+    fn jump_by_table(&self, table: &JumpTable, from: Position, d: usize) -> Option<Position> {
+        let distance = table.distance(from, DIRECTIONS[d]);
+        let reach = distance.unsigned_abs() as usize;
+        let (dx, dy) = DIRECTIONS[d];
+        // The table knows nothing about goals: stop where the goal is in line with the jump,
+        // or, when jumping diagonally, where it's straight ahead in one of the directions.
+        let towards_goal = self.goals.iter()
+            .filter_map(|&(gx, gy)| {
+                let (ox, oy) = (gx as isize - from.0 as isize, gy as isize - from.1 as isize);
+                if ox.signum() != dx || oy.signum() != dy {
+                    return None
+                }
+                let (ox, oy) = (ox.unsigned_abs(), oy.unsigned_abs());
+                Some (if is_diagonal(d) { ox.min(oy) } else { ox.max(oy) })
+            })
+            .filter(|&k| k <= reach)
+            .min();
+        match towards_goal {
+            Some (k) => Some (offset(from, d, k)),
+            None if distance > 0 => Some (offset(from, d, reach)),
+            None => None
+        }
+    }
+
+    /// Pop the best open node, skipping entries superseded by a cheaper path.
+    fn pop_open(&mut self) -> Option<Position> {
+        while let Some (Scored(_, pos)) = self.frontier.pop() {
+            if !self.visited.contains(&pos) {
+                return Some (pos)
+            }
+        }
+        None
+    }
+
+    fn expand(&mut self) {
+        let current = match self.pop_open() {
+            None => {
+                self.result = SearchState::Failed(Error::GoalUnreachable);
+                return
+            },
+            Some (current) => current
+        };
+        self.result = SearchState::InProgress;
+        debug!(target: "jps", "current: {:?}", current);
+        if self.map[current] == Field::Goal {
+            debug!(target: "jps", "goal found: {:?}", current);
+            let path = fill_in(&build_path::<MapField>(&self.steps, current));
+            self.stats.path(&path, self.g_score[&current]);
+            self.result = SearchState::Finished(path);
+            return
+        }
+        self.visited.insert(current);
+        self.stats.expanded += 1;
+        let g = self.g_score[&current];
+        let incoming = self.directions.get(&current).map_or(NO_PARENT, |&d| direction_index(d));
+        let moves = self.pruning.successors(neighbourhood(&self.map, current), incoming);
+        for d in (0 .. 8).filter(|&d| moves & 1 << d != 0) {
+            let jump_point = match self.table {
+                Some (ref table) => self.jump_by_table(table, current, d),
+                None => self.jump(current, d)
+            };
+            let next = match jump_point {
+                Some (next) => next,
+                None => continue
+            };
+            let tentative_g = g + Octile.estimate(self.map.delta(current, next));
+            let improved = match self.g_score.get(&next) {
+                None => true,
+                Some (&known) => tentative_g < known - 1e-9
+            };
+            if improved {
+                if self.visited.remove(&next) {
+                    self.stats.reopened += 1;
+                }
+                self.g_score.insert(next, tentative_g);
+                self.steps.insert(next, current);
+                self.directions.insert(next, DIRECTIONS[d]);
+                self.frontier.push(Scored(tentative_g + self.estimate(next), next));
+                self.stats.generated += 1;
+            }
+        }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+/// Put the fields passed by the straight or diagonal lines between consecutive jump points into the path.
+fn fill_in(jump_points: &[Position]) -> Vec<Position> {
+    let mut path = vec![];
+    for pair in jump_points.windows(2) {
+        let ((mut x, mut y), (to_x, to_y)) = (pair[0], pair[1]);
+        while (x, y) != (to_x, to_y) {
+            path.push((x, y));
+            x = (x as isize + (to_x as isize - x as isize).signum()) as usize;
+            y = (y as isize + (to_y as isize - y as isize).signum()) as usize;
+        }
+    }
+    path.extend(jump_points.last());
+    path
+}
+
+impl GraphSearch<Node2d> for JumpPointSearch {
+
+    type Node = MapField;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<MapField> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.visited.iter()
+            .map(|&pos| Node2d(pos, NodeState::Visited));
+        let frontier = self.frontier.iter()
+            .map(|&Scored(_, pos)| Node2d(pos, NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            let path = path.iter().map(|&pos| Node2d(pos, NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::map::{ self, ascii, generate };
+
+    #[test]
+    fn paths_cost_as_much_as_astar_under_all_rules() {
+        for fixture in &["map3", "map4", "map5"] {
+            let mut map = map::png::load(&format!("../test/fixtures/{}.png", fixture)).unwrap();
+            for &connectivity in &[Connectivity::Eight,
+                                   Connectivity::EightNoSqueezing,
+                                   Connectivity::EightNoCornerCutting] {
+                map.rules.connectivity = connectivity;
+                let mut astar = crate::astar_search(map.clone());
+                let expected = astar.run(None).map(|_| astar.stats.path_cost);
+                for (name, mut search) in [("jps", crate::jps(map.clone())),
+                                           ("jps+", crate::jps_plus(map.clone()))] {
+                    let found = search.run(None);
+                    match (&expected, &found) {
+                        (Ok (cost), Ok (path)) => {
+                            assert!((cost - search.stats.path_cost).abs() < 1e-9,
+                                    "{} {:?} {}: {} instead of {}",
+                                    fixture, connectivity, name, search.stats.path_cost, cost);
+                            let path = crate::path::normalise(&map, path.clone());
+                            map.validate_path(&path, &map.rules).unwrap();
+                        },
+                        (Err (_), Err (_)) => (),
+                        _ => panic!("{} {:?} {}: {:?} instead of {:?}",
+                                    fixture, connectivity, name, found.err(), expected.err())
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn respects_diagonal_gaps() {
+        let glyphs = ascii::Glyphs::default();
+        let mut map = ascii::parse("S#\n#G\n", &glyphs).unwrap();
+        assert_eq!(vec![(1,1), (0,0)], crate::jps_plus(map.clone()).run(None).unwrap());
+        map.rules.connectivity = Connectivity::EightNoSqueezing;
+        match crate::jps(map).run(None) {
+            Err (Error::GoalUnreachable) => (),
+            _ => panic!("expected GoalUnreachable")
+        }
+    }
+
+    #[test]
+    fn open_areas_take_few_jump_points() {
+        let map = generate::open(64, 48);
+        let mut search = crate::jps(map.clone());
+        search.run(None).unwrap();
+        let mut astar = crate::astar_search(map);
+        astar.run(None).unwrap();
+        assert_eq!(astar.stats.path_length, search.stats.path_length);
+        let jump_points = search.nodes().filter(|Node2d(_, state)| *state != NodeState::Path).count();
+        assert!(jump_points < 10, "{} jump points", jump_points);
+    }
+
+    #[test]
+    fn rejects_weighted_maps() {
+        let map = ascii::parse("S5G\n", &ascii::Glyphs::default()).unwrap();
+        match crate::jps(map.clone()).run(None) {
+            Err (Error::UnsupportedMap(_)) => (),
+            _ => panic!("expected UnsupportedMap")
+        }
+        assert!(JumpTable::new(&map).is_err());
+    }
+
+}
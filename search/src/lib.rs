@@ -1,16 +1,39 @@
 #[macro_use] extern crate log;
 
+use crate::astar::AStarSearch;
 use crate::bfs::BFSSearch;
+use crate::bidirectional::BidirectionalSearch;
+use crate::dfs::{ DFSSearch, IterativeDeepeningSearch };
+use crate::greedy::GreedySearch;
+use crate::idastar::IDAStarSearch;
+use crate::incremental::IncrementalSearch;
+use crate::jps::{ JumpPointSearch, JumpTable };
+use crate::heuristic::{ Heuristic, Octile };
+use crate::stats::SearchStats;
+use crate::theta::ThetaStarSearch;
 use crate::graph::Positionable;
-use crate::graph::{ SearchState, SearchNode };
-use map::{ Field, Map, Position };
+use crate::graph::{ Scored, SearchState, SearchNode };
+use map::{ Field, Map, PathViolation, Position, WorldShape };
 use std::collections::{ BinaryHeap, HashMap, HashSet };
-use std::fmt::Debug;
+use std::fmt::{ self, Debug };
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
 
+pub mod astar;
 pub mod bfs;
+pub mod bidirectional;
+pub mod dfs;
 pub mod graph;
+pub mod greedy;
+pub mod heuristic;
+pub mod idastar;
+pub mod incremental;
+pub mod jps;
 pub mod map;
+pub mod path;
+pub mod stats;
+pub mod theta;
 
 pub type Path = Vec<Position>;
 
@@ -82,19 +105,19 @@ fn moves_test() {
     // o.
     // ..
     assert_eq!(vec![(1,0), (1,1), (0,1)],
-               moves((0,0), (2, 2)));
+               moves((0,0), (2, 2), WorldShape::Bounded));
     // ..
     // o.
     assert_eq!(vec![(0,0), (1,0), (1,1)],
-               moves((0,1), (2, 2)));
+               moves((0,1), (2, 2), WorldShape::Bounded));
     // ..
     // .o
     assert_eq!(vec![(1,0), (0,1), (0,0)],
-               moves((1,1), (2, 2)));
+               moves((1,1), (2, 2), WorldShape::Bounded));
     // .o
     // ..
     assert_eq!(vec![(1,1), (0,1), (0,0)],
-               moves((1,0), (2, 2)));
+               moves((1,0), (2, 2), WorldShape::Bounded));
     // Numbers are indices into the example positions vector.
     // 7 0 1
     // 6 o 2
@@ -104,7 +127,7 @@ fn moves_test() {
                            (2,2), (1,2), (0,2),
                                          (0,1),
                                          (0,0)],
-               moves((1,1), (3, 3)));
+               moves((1,1), (3, 3), WorldShape::Bounded));
     // Dots are unreachable in 1 step.
     // . 0 1
     // . o 2
@@ -112,7 +135,7 @@ fn moves_test() {
     assert_eq!(vec![(0,0), (1,0),
                            (1,1),
                            (1,2), (0,2)],
-               moves((0,1), (3, 3)));
+               moves((0,1), (3, 3), WorldShape::Bounded));
     // . 4 0
     // . 3 o
     // . 2 1
@@ -120,34 +143,119 @@ fn moves_test() {
                     (2,2), (1,2),
                            (1,1),
                            (1,0)],
-               moves((2,1), (3, 3)));
+               moves((2,1), (3, 3), WorldShape::Bounded));
     // 4 o 0
     // 3 2 1
     // . . .
     assert_eq!(vec![(2,0),
                     (2,1), (1,1), (0,1),
                                   (0,0)],
-               moves((1,0), (3, 3)));
+               moves((1,0), (3, 3), WorldShape::Bounded));
     // . . .
     // 4 0 1
     // 3 o 2
     assert_eq!(vec![(1,1), (2,1),
                            (2,2), (0,2),
                                   (0,1)],
-               moves((1,2), (3, 3)));
+               moves((1,2), (3, 3), WorldShape::Bounded));
 }
 
-fn moves((px,py): Position, dimensions: (isize, isize)) -> Vec<Position> {
+#[test]
+fn torus_moves_test() {
+    // 5 . 4
+    // 3 . 2
+    // 1 . o
+    // ...where the last column and row wrap back to the first ones.
+    let mut got = moves((0,0), (3, 3), WorldShape::Torus);
+    got.sort();
+    assert_eq!(vec![(0,1), (0,2),
+                    (1,0), (1,1), (1,2),
+                    (2,0), (2,1), (2,2)], got);
+    // A dimension of 2 makes both neighbours along it the same field.
+    assert_eq!(vec![(0,1), (1,1), (1,0)],
+               moves((0,0), (2, 2), WorldShape::Torus));
+}
+
+#[cfg(test)]
+fn moves(pos: Position, dimensions: (isize, isize),
+         shape: WorldShape) -> Vec<Position> {
+    displacements(pos, dimensions, shape)
+        .into_iter()
+        .map(|(_, moved)| moved)
+        .collect()
+}
+
+/// Positions one move away from `pos`, along with the displacement leading to each of them.
+fn displacements((px,py): Position, dimensions: (isize, isize),
+                 shape: WorldShape) -> Vec<((isize, isize), Position)> {
     let (x0,y0) = (px as isize, py as isize);
-    Direction::iter()
-              .filter_map(|dir| { let (dx, dy) = dir.displacement();
-                                  crop((x0 + dx, y0 + dy), dimensions) })
-              .collect()
+    let mut moved: Vec<((isize, isize), Position)> = vec![];
+    for dir in Direction::iter() {
+        let (dx, dy) = dir.displacement();
+        if let Some (pos) = wrap((x0 + dx, y0 + dy), dimensions, shape) {
+            if pos != (px,py) && !moved.iter().any(|&(_, other)| other == pos) {
+                moved.push(((dx, dy), pos))
+            }
+        }
+    }
+    moved
 }
 
-fn crop((x0, y0): (isize, isize), (width, height): (isize, isize)) -> Option<Position> {
-    if x0 >= 0 && x0 < width && y0 >= 0 && y0 < height {
-        Some ((x0 as usize, y0 as usize))
+#[test]
+fn allowed_moves_test() {
+    use map::{ Connectivity, MovementRules };
+    let with = |connectivity, fields| {
+        let mut map = Map::new(2, 2, fields);
+        map.rules = MovementRules { connectivity, ..MovementRules::default() };
+        allowed_moves(&map, (0,0))
+    };
+    // .#
+    // #.
+    let gap = vec![Field::Passable, Field::Impassable,
+                   Field::Impassable, Field::Passable];
+    assert_eq!(vec![(1,1)], with(Connectivity::Eight, gap.clone()));
+    assert_eq!(Vec::<Position>::new(), with(Connectivity::EightNoSqueezing, gap.clone()));
+    assert_eq!(Vec::<Position>::new(), with(Connectivity::EightNoCornerCutting, gap.clone()));
+    assert_eq!(Vec::<Position>::new(), with(Connectivity::Four, gap));
+    // .#
+    // ..
+    let corner = vec![Field::Passable, Field::Impassable,
+                      Field::Passable, Field::Passable];
+    assert_eq!(vec![(1,1), (0,1)], with(Connectivity::Eight, corner.clone()));
+    assert_eq!(vec![(1,1), (0,1)], with(Connectivity::EightNoSqueezing, corner.clone()));
+    assert_eq!(vec![(0,1)], with(Connectivity::EightNoCornerCutting, corner.clone()));
+    assert_eq!(vec![(0,1)], with(Connectivity::Four, corner));
+}
+
+/// Passable neighbours of `pos` reachable in one move under the map's movement rules.
+fn allowed_moves(map: &Map, (px,py): Position) -> Vec<Position> {
+    let (x0,y0) = (px as isize, py as isize);
+    let dimensions = map.isize_dimensions();
+    let shape = map.rules.shape;
+    let passable = |(dx, dy): (isize, isize)| {
+        match wrap((x0 + dx, y0 + dy), dimensions, shape) {
+            None => false,
+            Some (pos) => map[pos].is_passable()
+        }
+    };
+    displacements((px,py), dimensions, shape)
+        .into_iter()
+        .filter(|&((dx, dy), moved)| {
+            map[moved].is_passable()
+                && map.rules.connectivity.allows((dx, dy),
+                                                 passable((dx, 0)),
+                                                 passable((0, dy)))
+        })
+        .map(|(_, moved)| moved)
+        .collect()
+}
+
+fn wrap((x0, y0): (isize, isize), (width, height): (isize, isize),
+        shape: WorldShape) -> Option<Position> {
+    let x = if shape.wraps_horizontally() { x0.rem_euclid(width) } else { x0 };
+    let y = if shape.wraps_vertically() { y0.rem_euclid(height) } else { y0 };
+    if x >= 0 && x < width && y >= 0 && y < height {
+        Some ((x as usize, y as usize))
     } else {
         None
     }
@@ -155,7 +263,62 @@ fn crop((x0, y0): (isize, isize), (width, height): (isize, isize)) -> Option<Pos
 
 #[derive(Clone, Debug)]
 pub enum Error {
-    GoalUnreachable
+    /// The map has no start field.
+    NoStart,
+    /// The map has no goal field.
+    NoGoal,
+    /// The algorithm can only search from a single start field.
+    MultipleStartsUnsupported,
+    /// Searching finished without reaching any goal.
+    GoalUnreachable,
+    /// Reading or writing a file failed.
+    Io(Arc<std::io::Error>),
+    /// The image can't be decoded or is in a format which can't be turned into a map.
+    UnsupportedPng(String),
+    /// A text map or scenario file can't be parsed.
+    Malformed(String),
+    /// A position lies outside of the map.
+    OutOfBounds(Position),
+    /// The search was stopped after taking the allowed number of steps.
+    BudgetExhausted,
+    /// The algorithm can't search this kind of map.
+    UnsupportedMap(String),
+    /// A path can't be followed on a map, because of the position at the given index.
+    InvalidPath(usize, PathViolation)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoStart => write!(f, "no start field"),
+            Error::NoGoal => write!(f, "no goal field"),
+            Error::MultipleStartsUnsupported =>
+                write!(f, "searching from multiple start fields is not supported"),
+            Error::GoalUnreachable => write!(f, "goal unreachable"),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::UnsupportedPng(reason) => write!(f, "unsupported PNG: {}", reason),
+            Error::Malformed(reason) => write!(f, "malformed input: {}", reason),
+            Error::OutOfBounds((x,y)) => write!(f, "position ({}, {}) out of map bounds", x, y),
+            Error::BudgetExhausted => write!(f, "step budget exhausted"),
+            Error::UnsupportedMap(reason) => write!(f, "unsupported map: {}", reason),
+            Error::InvalidPath(step, violation) => write!(f, "invalid path at step {}: {}", step, violation)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some (e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(Arc::new(e))
+    }
 }
 
 pub type SearchResult = std::result::Result<Search, Error>;
@@ -164,14 +327,29 @@ pub struct Search {
     pub start: Vec<Position>,
     pub goals: Vec<Position>,
     pub paths: Vec<Path>,
-    pub visited: Vec<Position>
+    pub visited: Vec<Position>,
+    /// Statistics of the search; path length and cost refer to the first of `paths`.
+    pub stats: SearchStats
 }
 
-fn distance((x1,y1): Position, (x2,y2): Position) -> isize {
-    let (fx1, fy1, fx2, fy2) = (x1 as f64, y1 as f64, x2 as f64, y2 as f64);
-    let dx = fx2 - fx1;
-    let dy = fy2 - fy1;
-    (dx * dx + dy * dy).sqrt().round() as isize
+/// Octile distance, which never overestimates the cost of moving across unweighted fields.
+fn distance(map: &Map, from: Position, to: Position) -> f64 {
+    Octile.estimate(map.delta(from, to))
+}
+
+fn distance_to_nearest(map: &Map, from: Position, goals: &[Position]) -> f64 {
+    goals.iter()
+        .map(|goal| distance(map, from, *goal))
+        .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or(0.0)
+}
+
+/// Cost of a single move between adjacent positions:
+/// the cost of the entered field, times the square root of 2 for diagonal moves.
+pub fn step_cost(map: &Map, (x1,y1): Position, (x2,y2): Position) -> f64 {
+    let cost = map[(x2,y2)].cost();
+    if x1 != x2 && y1 != y2 { cost * std::f64::consts::SQRT_2 }
+    else { cost }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -211,125 +389,447 @@ impl SearchNode for MapField {
     }
 
     fn neighbours(&self) -> Vec<MapField> {
-        moves(self.pos, self.map.isize_dimensions())
+        allowed_moves(&self.map, self.pos)
             .iter()
-            .filter(|moved| self.map[**moved].is_passable())
             .map(|moved| MapField { map: self.map.clone(),
                                     pos: *moved })
             .collect()
     }
 
+    fn cost(&self, next: &MapField) -> f64 {
+        step_cost(&self.map, self.pos, next.pos)
+    }
+
 }
 
-pub fn bfs<'a>(map: Map) -> BFSSearch<MapField> {
-    let rc_map = Rc::new(map);
-    let start: Vec<MapField> = rc_map.start()
-        .iter()
+fn start_fields(rc_map: &Rc<Map>) -> Vec<MapField> {
+    fields(rc_map, &rc_map.start())
+}
+
+fn fields(rc_map: &Rc<Map>, positions: &[Position]) -> Vec<MapField> {
+    positions.iter()
         .map(|pos| MapField { pos: *pos,
                               map: rc_map.clone() })
-        .collect();
-    BFSSearch { result: SearchState::NotStarted,
-                frontier: start.clone(),
-                visited: start.iter().map(|field| field.pos).collect(),
-                steps: HashMap::new() }
+        .collect()
+}
+
+/// Fail searches on maps without start or goal fields right away.
+fn initial_state<V: SearchNode>(map: &Map) -> SearchState<V> {
+    if map.start().is_empty() {
+        SearchState::Failed(Error::NoStart)
+    } else if map.goals().is_empty() {
+        SearchState::Failed(Error::NoGoal)
+    } else {
+        SearchState::NotStarted
+    }
+}
+
+pub fn bfs<'a>(map: Map) -> BFSSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = BFSSearch::new(start_fields(&rc_map));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+pub fn dfs(map: Map) -> DFSSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = DFSSearch::new(start_fields(&rc_map));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+pub fn depth_limited_dfs(map: Map, limit: usize) -> DFSSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = DFSSearch::depth_limited(start_fields(&rc_map), limit);
+    search.result = initial_state(&rc_map);
+    search
 }
 
-fn appraise(pos: Position, goal: Position) -> (isize, Position) {
-    ( -distance(pos, goal), pos )
+/// Iterative deepening depth-first search.
+pub fn iddfs(map: Map) -> IterativeDeepeningSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = IterativeDeepeningSearch::new(start_fields(&rc_map));
+    search.current.result = initial_state(&rc_map);
+    search
+}
+
+fn distance_to_goals(rc_map: &Rc<Map>, heuristic: impl Heuristic + 'static)
+        -> Rc<dyn Fn(&MapField) -> f64> {
+    distance_to(rc_map.goals(), Rc::new(heuristic))
+}
+
+/// Estimate of the distance to the nearest of `targets`.
+fn distance_to(targets: Vec<Position>, heuristic: Rc<dyn Heuristic>)
+        -> Rc<dyn Fn(&MapField) -> f64> {
+    Rc::new(move |field: &MapField| {
+        targets.iter()
+            .map(|target| heuristic.estimate(field.map.delta(field.pos, *target)))
+            .fold(f64::INFINITY, f64::min)
+    })
+}
+
+pub fn astar_search(map: Map) -> AStarSearch<MapField> {
+    astar_with(map, Octile)
+}
+
+pub fn astar_with(map: Map, heuristic: impl Heuristic + 'static) -> AStarSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = AStarSearch::new(start_fields(&rc_map),
+                                      distance_to_goals(&rc_map, heuristic));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+/// Uniform-cost search: A* without a heuristic, expanding the cheapest paths first.
+pub fn dijkstra(map: Map) -> AStarSearch<MapField> {
+    astar_with(map, heuristic::Zero)
+}
+
+/// Breadth-first searches from the start and from the goals towards each other.
+pub fn bidirectional_bfs(map: Map) -> BidirectionalSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = BidirectionalSearch::breadth_first(start_fields(&rc_map),
+                                                        fields(&rc_map, &rc_map.goals()));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+pub fn bidirectional_astar(map: Map) -> BidirectionalSearch<MapField> {
+    bidirectional_astar_with(map, Octile)
+}
+
+/// A* from the start and from the goals towards each other.
+pub fn bidirectional_astar_with(map: Map, heuristic: impl Heuristic + 'static)
+        -> BidirectionalSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let heuristic: Rc<dyn Heuristic> = Rc::new(heuristic);
+    let mut search = BidirectionalSearch::astar(start_fields(&rc_map),
+                                                fields(&rc_map, &rc_map.goals()),
+                                                distance_to(rc_map.goals(), heuristic.clone()),
+                                                distance_to(rc_map.start(), heuristic));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+pub fn idastar_search(map: Map) -> IDAStarSearch<MapField> {
+    idastar_with(map, Octile)
+}
+
+pub fn idastar_with(map: Map, heuristic: impl Heuristic + 'static) -> IDAStarSearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = IDAStarSearch::new(start_fields(&rc_map),
+                                        distance_to_goals(&rc_map, heuristic));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+/// Jump point search on a bounded, uniform-cost map with diagonal moves.
+pub fn jps(map: Map) -> JumpPointSearch {
+    jump_point_search(map, None)
+}
+
+/// Jump point search with jump distances precomputed for the whole map.
+pub fn jps_plus(map: Map) -> JumpPointSearch {
+    match JumpTable::new(&map) {
+        Ok (table) => jump_point_search(map, Some (Rc::new(table))),
+        Err (_) => jump_point_search(map, None)
+    }
+}
+
+/// JPS+ reusing a jump table made from the same map.
+pub fn jps_plus_with(map: Map, table: Rc<JumpTable>) -> JumpPointSearch {
+    jump_point_search(map, Some (table))
+}
+
+fn jump_point_search(map: Map, table: Option<Rc<JumpTable>>) -> JumpPointSearch {
+    let rc_map = Rc::new(map);
+    let mut search = JumpPointSearch::new(rc_map.clone(), table);
+    search.result = match jps::check_map(&rc_map) {
+        Ok (()) => initial_state(&rc_map),
+        Err (e) => SearchState::Failed(e)
+    };
+    search
+}
+
+/// Theta*, finding any-angle paths of waypoints which see each other.
+pub fn theta_star(map: Map) -> ThetaStarSearch {
+    any_angle_search(map, false)
+}
+
+/// Lazy Theta*, which checks the line of sight only between expanded fields and their parents.
+pub fn lazy_theta_star(map: Map) -> ThetaStarSearch {
+    any_angle_search(map, true)
+}
+
+fn any_angle_search(map: Map, lazy: bool) -> ThetaStarSearch {
+    let rc_map = Rc::new(map);
+    let mut search = ThetaStarSearch::new(rc_map.clone(), lazy);
+    search.result = match theta::check_map(&rc_map) {
+        Ok (()) => initial_state(&rc_map),
+        Err (e) => SearchState::Failed(e)
+    };
+    search
+}
+
+/// Lifelong Planning A*, which repairs its path after fields are updated.
+pub fn lpa_star(map: Map) -> IncrementalSearch {
+    let mut search = IncrementalSearch::lpa_star(map);
+    search.result = initial_state(&search.map);
+    search
+}
+
+/// D* Lite, which repairs its path after fields are updated or the start moves.
+pub fn dstar_lite(map: Map) -> IncrementalSearch {
+    let mut search = IncrementalSearch::dstar_lite(map);
+    search.result = if search.targets.len() > 1 {
+        SearchState::Failed(Error::MultipleStartsUnsupported)
+    } else {
+        initial_state(&search.map)
+    };
+    search
+}
+
+pub fn greedy_search(map: Map) -> GreedySearch<MapField> {
+    greedy_with(map, Octile)
+}
+
+pub fn greedy_with(map: Map, heuristic: impl Heuristic + 'static) -> GreedySearch<MapField> {
+    let rc_map = Rc::new(map);
+    let mut search = GreedySearch::new(start_fields(&rc_map),
+                                       distance_to_goals(&rc_map, heuristic));
+    search.result = initial_state(&rc_map);
+    search
+}
+
+fn appraise(map: &Map, pos: Position, goals: &[Position]) -> Scored<Position> {
+    Scored(distance_to_nearest(map, pos, goals), pos)
+}
+
+#[test]
+fn search_errors_test() {
+    use crate::graph::GraphSearch;
+    let map = Map::new(3, 1, vec![Field::Start, Field::Passable, Field::Goal]);
+    match astar(vec![], vec![(2,0)], &map) {
+        Err (Error::NoStart) => (),
+        _ => panic!("expected NoStart")
+    }
+    match greedy(vec![(0,0)], vec![(3,0)], &map) {
+        Err (Error::OutOfBounds((3,0))) => (),
+        _ => panic!("expected OutOfBounds")
+    }
+    match bfs(map.clone()).run(Some (1)) {
+        Err (Error::BudgetExhausted) => (),
+        _ => panic!("expected BudgetExhausted")
+    }
+    let no_goal = Map::new(2, 1, vec![Field::Start, Field::Passable]);
+    match dijkstra(no_goal).run(None) {
+        Err (Error::NoGoal) => (),
+        _ => panic!("expected NoGoal")
+    }
+}
+
+#[test]
+fn multiple_goals_test() {
+    use crate::graph::GraphSearch;
+    // S...G..S.G
+    let mut fields = vec![Field::Passable; 10];
+    fields[0] = Field::Start;
+    fields[7] = Field::Start;
+    fields[4] = Field::Goal;
+    fields[9] = Field::Goal;
+    let map = Map::new(10, 1, fields);
+    let nearest = astar(map.start(), map.goals(), &map).unwrap();
+    assert_eq!(vec![vec![(9,0), (8,0), (7,0)]], nearest.paths);
+    let every = astar_multi(map.start(), map.goals(), &map, GoalMode::Every).unwrap();
+    assert_eq!(vec![vec![(9,0), (8,0), (7,0)],
+                    vec![(4,0), (5,0), (6,0), (7,0)]], every.paths);
+    let every = greedy_multi(map.start(), map.goals(), &map, GoalMode::Every).unwrap();
+    assert_eq!(2, every.paths.len());
+    let mut search = dijkstra(map);
+    assert_eq!(vec![(9,0), (8,0), (7,0)], search.run(None).unwrap());
+}
+
+#[test]
+fn oneshot_astar_is_optimal_test() {
+    use crate::graph::GraphSearch;
+    // Rounded straight-line distances overestimate diagonal moves and led to a longer path here.
+    let rows = ["..#..G", "S.....", "S#..#.", "......", "#.S..."];
+    let fields = rows.iter().flat_map(|row| row.chars()).map(|c| match c {
+        'S' => Field::Start,
+        'G' => Field::Goal,
+        '#' => Field::Impassable,
+        _ => Field::Passable
+    }).collect();
+    let map = Map::new(6, 5, fields);
+    let cost = |path: &Path| -> f64 {
+        path.windows(2).map(|step| step_cost(&map, step[1], step[0])).sum()
+    };
+    let oneshot = astar(map.start(), map.goals(), &map).unwrap();
+    let optimal = dijkstra(map.clone()).run(None).unwrap();
+    assert!((cost(&oneshot.paths[0]) - cost(&optimal)).abs() < 1e-9);
+    assert!((cost(&optimal) - (1.0 + 3.0 * std::f64::consts::SQRT_2)).abs() < 1e-9);
+}
+
+/// Check the start and goal positions passed to one-shot searches.
+fn check_endpoints(start: &[Position], goals: &[Position], map: &Map) -> Result<(), Error> {
+    if start.is_empty() {
+        return Err (Error::NoStart)
+    }
+    if goals.is_empty() {
+        return Err (Error::NoGoal)
+    }
+    match start.iter().chain(goals.iter()).find(|&&(x,y)| x >= map.width || y >= map.height) {
+        Some (&pos) => Err (Error::OutOfBounds(pos)),
+        None => Ok (())
+    }
+}
+
+/// Whether one-shot searches stop at the nearest goal or go on until all goals are reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoalMode {
+    Nearest,
+    Every
 }
 
 pub fn greedy(start: Vec<Position>, vgoals: Vec<Position>,
               map: &Map) -> SearchResult {
+    greedy_multi(start, vgoals, map, GoalMode::Nearest)
+}
+
+pub fn greedy_multi(start: Vec<Position>, vgoals: Vec<Position>,
+                    map: &Map, mode: GoalMode) -> SearchResult {
+    let started = Instant::now();
     let map = map.clone();
-    assert_eq!(1, start.len());
-    assert_eq!(1, vgoals.len());
-    let mut pq = BinaryHeap::new();
-    pq.push( appraise(start[0], vgoals[0]) );
+    check_endpoints(&start, &vgoals, &map)?;
+    let mut pq: BinaryHeap<Scored<Position>> = start.iter()
+        .map(|pos| appraise(&map, *pos, &vgoals))
+        .collect();
     let goals = vec_to_set(vgoals.clone());
     let mut visited = vec_to_set(start.clone());
     let mut steps = HashMap::new();
+    let mut g_score: HashMap<Position, f64> = start.iter().map(|pos| (*pos, 0.0)).collect();
+    let mut stats = SearchStats { generated: pq.len(), max_frontier: pq.len(),
+                                  ..SearchStats::default() };
+    let mut paths = vec![];
     loop {
-        let (_, pos) = match pq.pop() {
+        let pos = match pq.pop() {
             None => break,
-            Some (pos) => pos
+            Some (Scored(_, pos)) => pos
         };
+        stats.steps += 1;
         debug!("visited: {:?}", visited);
         debug!("current: {:?}", pos);
         debug!("steps  : {:?}", steps);
         if goals.contains(&pos) {
             let path = reconstruct_path(pos, &steps);
-            return Ok (Search { start: start,
-                                goals: vgoals,
-                                paths: vec![path],
-                                visited: visited.into_iter().collect() })
+            if paths.is_empty() {
+                stats.path(&path, g_score[&pos]);
+            }
+            paths.push(path);
+            if mode == GoalMode::Nearest || paths.len() == goals.len() {
+                break
+            }
         }
-        let moves: Vec<(isize, Position)> = moves(pos, map.isize_dimensions()).iter()
-            .map(|new_pos| {
-                if !map[*new_pos].is_passable() { None }
-                else { Some (appraise(*new_pos, vgoals[0])) }
-            }).filter_map(|new_pos| new_pos).collect();
-        for &(cost, new_pos) in moves.iter() {
+        stats.expanded += 1;
+        let moves: Vec<Scored<Position>> = allowed_moves(&map, pos).iter()
+            .map(|new_pos| appraise(&map, *new_pos, &vgoals)).collect();
+        for Scored(estimate, new_pos) in moves {
             if !visited.contains(&new_pos) {
-                pq.push((cost, new_pos));
+                pq.push(Scored(estimate, new_pos));
                 visited.insert(new_pos);
                 steps.insert(new_pos, pos);
+                g_score.insert(new_pos, g_score[&pos] + step_cost(&map, pos, new_pos));
+                stats.generated += 1;
             }
         }
+        stats.frontier_size(pq.len());
     }
-    Err (Error::GoalUnreachable)
+    stats.wall_time = started.elapsed();
+    finish_oneshot(start, vgoals, paths, visited, stats)
 }
 
-#[allow(unused_parens)]
 pub fn astar(start: Vec<Position>, vgoals: Vec<Position>,
              map: &Map) -> SearchResult {
+    astar_multi(start, vgoals, map, GoalMode::Nearest)
+}
+
+pub fn astar_multi(start: Vec<Position>, vgoals: Vec<Position>,
+                   map: &Map, mode: GoalMode) -> SearchResult {
+    let started = Instant::now();
     let map = map.clone();
-    assert_eq!(1, start.len());
-    assert_eq!(1, vgoals.len());
+    check_endpoints(&start, &vgoals, &map)?;
     let goals = vec_to_set(vgoals.clone());
     let mut visited = vec_to_set(start.clone());
+    let mut closed = HashSet::new();
     let mut steps = HashMap::new();
     let mut g_score = HashMap::new();
-    let start0 = start[0].clone();
-    g_score.insert(start0, 0);
-    let mut f_score = HashMap::new();
-    f_score.insert(start0, g_score[&start0] + distance(start0, vgoals[0]));
     let mut pq = BinaryHeap::new();
-    pq.push( ( - f_score[&start0], start0 ) );
+    for &pos in start.iter() {
+        g_score.insert(pos, 0.0);
+        pq.push( Scored(distance_to_nearest(&map, pos, &vgoals), pos) );
+    }
+    let mut stats = SearchStats { generated: pq.len(), max_frontier: pq.len(),
+                                  ..SearchStats::default() };
+    let mut paths = vec![];
     loop {
         let pos = match pq.pop() {
             None => break,
-            Some ((_, pos)) => pos
+            Some (Scored(_, pos)) => pos
         };
+        if !closed.insert(pos) {
+            continue
+        }
+        stats.steps += 1;
         debug!("visited: {:?}", visited);
         debug!("current: {:?}", pos);
         debug!("steps  : {:?}", steps);
         if goals.contains(&pos) {
             let path = reconstruct_path(pos, &steps);
-            return Ok (Search { start: start,
-                                goals: vgoals,
-                                paths: vec![path],
-                                visited: visited.into_iter().collect() })
+            if paths.is_empty() {
+                stats.path(&path, g_score[&pos]);
+            }
+            paths.push(path);
+            if mode == GoalMode::Nearest || paths.len() == goals.len() {
+                break
+            }
         }
-        let moves: Vec<Position> = moves(pos, map.isize_dimensions()).iter()
-            .filter_map(|new_pos|
-                        if map[*new_pos].is_passable() { Some (*new_pos) }
-                        else { None }).collect();
+        stats.expanded += 1;
+        let moves: Vec<Position> = allowed_moves(&map, pos);
         for new_pos in moves.iter() {
-            let tentative_g_score = g_score[&pos] + 1;
-            if (!visited.contains(new_pos)
-                || (g_score.contains_key(new_pos)
-                    && tentative_g_score < g_score[new_pos])) {
+            let tentative_g_score = g_score[&pos] + step_cost(&map, pos, *new_pos);
+            let improved = match g_score.get(new_pos) {
+                None => true,
+                Some (&known) => tentative_g_score < known - 1e-9
+            };
+            if improved {
+                if closed.remove(new_pos) {
+                    stats.reopened += 1;
+                }
                 g_score.insert(*new_pos, tentative_g_score);
-                f_score.insert
-                    (*new_pos, (tentative_g_score
-                                + distance(*new_pos, vgoals[0])));
-                pq.push((- f_score[new_pos], *new_pos));
+                let f_score = tentative_g_score
+                    + distance_to_nearest(&map, *new_pos, &vgoals);
+                pq.push(Scored(f_score, *new_pos));
                 visited.insert(*new_pos);
                 steps.insert(*new_pos, pos);
+                stats.generated += 1;
             }
         }
+        stats.frontier_size(pq.len());
+    }
+    stats.wall_time = started.elapsed();
+    finish_oneshot(start, vgoals, paths, visited, stats)
+}
+
+fn finish_oneshot(start: Vec<Position>, goals: Vec<Position>, paths: Vec<Path>,
+                  visited: HashSet<Position>, stats: SearchStats) -> SearchResult {
+    if paths.is_empty() {
+        return Err (Error::GoalUnreachable)
     }
-    Err (Error::GoalUnreachable)
+    Ok (Search { start,
+                 goals,
+                 paths,
+                 visited: visited.into_iter().collect(),
+                 stats })
 }
 
 #[inline]
@@ -0,0 +1,162 @@
+//! Plain text maps, handy for carrying small maps inline in tests and bug reports.
+//!
+//! ```text
+//! S..#
+//! .#.G
+//! ```
+
+use super::{ Field, Map, Position, MAX_WEIGHT };
+use super::super::{ Error, Search };
+use std::fs;
+
+/// Characters standing for map fields and search overlays.
+///
+/// Weighted fields are always written as digits: `1` to `9` stand for their weight,
+/// `0` for `MAX_WEIGHT`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    pub start: char,
+    pub goal: char,
+    pub passable: char,
+    pub impassable: char,
+    /// Only used when writing search results.
+    pub path: char,
+    /// Only used when writing search results.
+    pub visited: char
+}
+
+impl Default for Glyphs {
+    fn default() -> Glyphs {
+        Glyphs { start: 'S', goal: 'G', passable: '.', impassable: '#', path: '*', visited: 'o' }
+    }
+}
+
+pub fn load(source: &str, glyphs: &Glyphs) -> Result<Map, Error> {
+    parse(&fs::read_to_string(source)?, glyphs)
+}
+
+/// Parse a map with one line per row.
+/// Empty lines around the map are ignored.
+pub fn parse(text: &str, glyphs: &Glyphs) -> Result<Map, Error> {
+    let rows: Vec<&str> = text.trim_matches('\n').lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    let width = rows.first().map(|row| row.chars().count()).unwrap_or(0);
+    let mut fields = Vec::with_capacity(width * rows.len());
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != width {
+            return Err (Error::Malformed(format!("row {} is not {} fields wide", y, width)))
+        }
+        for glyph in row.chars() {
+            fields.push(glyph_to_field(glyph, glyphs)?)
+        }
+    }
+    Ok (Map::new(width, rows.len(), fields))
+}
+
+fn glyph_to_field(glyph: char, glyphs: &Glyphs) -> Result<Field, Error> {
+    match glyph {
+        g if g == glyphs.start => Ok (Field::Start),
+        g if g == glyphs.goal => Ok (Field::Goal),
+        g if g == glyphs.passable => Ok (Field::Passable),
+        g if g == glyphs.impassable => Ok (Field::Impassable),
+        '0' => Ok (Field::Weighted(MAX_WEIGHT)),
+        g => match g.to_digit(10) {
+            Some (weight) => Ok (Field::Weighted(weight as u8)),
+            None => Err (Error::Malformed(format!("unknown glyph {:?}", g)))
+        }
+    }
+}
+
+fn field_to_glyph(field: Field, glyphs: &Glyphs) -> char {
+    match field {
+        Field::Start => glyphs.start,
+        Field::Goal => glyphs.goal,
+        Field::Impassable => glyphs.impassable,
+        Field::Weighted(weight) => std::char::from_digit((weight % 10) as u32, 10).unwrap_or('?'),
+        _ => glyphs.passable
+    }
+}
+
+pub fn save(map: &Map, dest: &str, glyphs: &Glyphs) -> Result<(), Error> {
+    Ok (fs::write(dest, write(map, glyphs))?)
+}
+
+pub fn write(map: &Map, glyphs: &Glyphs) -> String {
+    render(map, glyphs, |_| ())
+}
+
+/// Write the map with visited fields and paths of the search drawn on top of it.
+pub fn write_search(map: &Map, search: &Search, glyphs: &Glyphs) -> String {
+    render(map, glyphs, |rows| {
+        draw(rows, &search.visited, glyphs.visited);
+        for path in search.paths.iter() {
+            draw(rows, path, glyphs.path)
+        }
+        draw(rows, &search.start, glyphs.start);
+        draw(rows, &search.goals, glyphs.goal);
+    })
+}
+
+fn render(map: &Map, glyphs: &Glyphs, overlay: impl FnOnce(&mut Vec<Vec<char>>)) -> String {
+    let mut rows: Vec<Vec<char>> = map.fields.chunks(map.width.max(1))
+        .map(|row| row.iter().map(|&f| field_to_glyph(f, glyphs)).collect())
+        .collect();
+    overlay(&mut rows);
+    let mut text = String::with_capacity((map.width + 1) * map.height);
+    for row in rows {
+        text.extend(row);
+        text.push('\n');
+    }
+    text
+}
+
+fn draw(rows: &mut [Vec<char>], positions: &[Position], glyph: char) {
+    for &(x, y) in positions {
+        if let Some (cell) = rows.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *cell = glyph
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parses_fields_and_weights() {
+        let map = parse("S..#\n.#5G\n", &Glyphs::default()).unwrap();
+        assert_eq!((4, 2), (map.width, map.height));
+        assert_eq!(vec![(0,0)], map.start());
+        assert_eq!(vec![(3,1)], map.goals());
+        assert_eq!(Field::Impassable, map[(1,1)]);
+        assert_eq!(Field::Weighted(5), map[(2,1)]);
+    }
+
+    #[test]
+    fn custom_glyphs_round_trip() {
+        let glyphs = Glyphs { impassable: '@', passable: ' ', ..Glyphs::default() };
+        let text = "S  @\n @0G\n";
+        assert_eq!(Field::Weighted(MAX_WEIGHT), parse(text, &glyphs).unwrap()[(2,1)]);
+        assert_eq!(text, write(&parse(text, &glyphs).unwrap(), &glyphs));
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        match parse("S..\n.G", &Glyphs::default()) {
+            Err (Error::Malformed(_)) => (),
+            _ => panic!("expected Malformed")
+        }
+    }
+
+    #[test]
+    fn draws_search_overlays() {
+        let glyphs = Glyphs::default();
+        let map = parse("S...\n###.\nG...\n", &glyphs).unwrap();
+        let result = crate::astar(map.start(), map.goals(), &map).unwrap();
+        assert_eq!("S**.\n###*\nG**.\n",
+                   write_search(&map, &Search { visited: vec![], ..result }, &glyphs));
+    }
+
+}
@@ -0,0 +1,400 @@
+//! Procedural maps.
+//!
+//! Every generator is deterministic: the same size and seed always give the same map.
+//! Start and goal fields are placed so that the goal is reachable from the start
+//! even with 4-connectivity, as far from each other as the map allows.
+
+use super::{ Field, Map, Position, MAX_WEIGHT };
+use std::collections::VecDeque;
+
+/// Algorithm carving a perfect maze, i.e. one with exactly one path between any two cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MazeAlgorithm {
+    /// Long, winding corridors with few dead ends.
+    RecursiveBacktracker,
+    /// Many short dead ends branching off.
+    Prim,
+    /// Uniformly spread, short dead ends.
+    Kruskal
+}
+
+/// A map without obstacles, with start and goal in opposite corners.
+pub fn open(width: usize, height: usize) -> Map {
+    let mut map = Map::new(width, height, vec![Field::Passable; width * height]);
+    if width > 0 && height > 0 {
+        map[(0, 0)] = Field::Start;
+        map[(width - 1, height - 1)] = Field::Goal;
+    }
+    map
+}
+
+/// A maze of one field wide corridors.
+/// Corridors run through odd coordinates, so odd sizes leave no thick outer walls.
+pub fn maze(width: usize, height: usize, algorithm: MazeAlgorithm, seed: u64) -> Map {
+    let mut rng = Rng::new(seed);
+    let mut map = Map::new(width, height, vec![Field::Impassable; width * height]);
+    let (cols, rows) = (width.saturating_sub(1) / 2, height.saturating_sub(1) / 2);
+    let cell = |i: usize| (2 * (i % cols) + 1, 2 * (i / cols) + 1);
+    for i in 0 .. cols * rows {
+        map[cell(i)] = Field::Passable
+    }
+    let neighbours = |i: usize| {
+        let (c, r) = (i % cols, i / cols);
+        let mut n = vec![];
+        if c > 0 { n.push(i - 1) }
+        if c + 1 < cols { n.push(i + 1) }
+        if r > 0 { n.push(i - cols) }
+        if r + 1 < rows { n.push(i + cols) }
+        n
+    };
+    let connect = |map: &mut Map, a: usize, b: usize| {
+        let ((ax, ay), (bx, by)) = (cell(a), cell(b));
+        map[((ax + bx) / 2, (ay + by) / 2)] = Field::Passable
+    };
+    if cols * rows > 0 {
+        match algorithm {
+            MazeAlgorithm::RecursiveBacktracker => {
+                let mut visited = vec![false; cols * rows];
+                let mut stack = vec![rng.below(cols * rows)];
+                visited[stack[0]] = true;
+                while let Some (&current) = stack.last() {
+                    let unvisited: Vec<usize> = neighbours(current).into_iter()
+                        .filter(|&n| !visited[n])
+                        .collect();
+                    if unvisited.is_empty() {
+                        stack.pop();
+                    } else {
+                        let next = unvisited[rng.below(unvisited.len())];
+                        connect(&mut map, current, next);
+                        visited[next] = true;
+                        stack.push(next)
+                    }
+                }
+            },
+            MazeAlgorithm::Prim => {
+                let mut in_maze = vec![false; cols * rows];
+                let first = rng.below(cols * rows);
+                in_maze[first] = true;
+                let mut walls: Vec<(usize, usize)> = neighbours(first).into_iter()
+                    .map(|n| (first, n))
+                    .collect();
+                while !walls.is_empty() {
+                    let (from, to) = walls.swap_remove(rng.below(walls.len()));
+                    if in_maze[to] {
+                        continue
+                    }
+                    connect(&mut map, from, to);
+                    in_maze[to] = true;
+                    walls.extend(neighbours(to).into_iter()
+                                 .filter(|&n| !in_maze[n])
+                                 .map(|n| (to, n)));
+                }
+            },
+            MazeAlgorithm::Kruskal => {
+                let mut sets: Vec<usize> = (0 .. cols * rows).collect();
+                let mut walls: Vec<(usize, usize)> = (0 .. cols * rows)
+                    .flat_map(|i| neighbours(i).into_iter().filter(move |&n| n > i).map(move |n| (i, n)))
+                    .collect();
+                rng.shuffle(&mut walls);
+                for (a, b) in walls {
+                    let (root_a, root_b) = (find(&mut sets, a), find(&mut sets, b));
+                    if root_a != root_b {
+                        sets[root_a] = root_b;
+                        connect(&mut map, a, b)
+                    }
+                }
+            }
+        }
+    }
+    place_endpoints(&mut map, &mut rng);
+    map
+}
+
+fn find(sets: &mut [usize], mut i: usize) -> usize {
+    while sets[i] != i {
+        sets[i] = sets[sets[i]];
+        i = sets[i]
+    }
+    i
+}
+
+/// Rectangular rooms joined by corridors.
+pub fn dungeon(width: usize, height: usize, seed: u64) -> Map {
+    let mut rng = Rng::new(seed);
+    let mut map = Map::new(width, height, vec![Field::Impassable; width * height]);
+    let max_side = (width.min(height) / 4).max(3);
+    let mut rooms: Vec<(usize, usize, usize, usize)> = vec![];
+    for _ in 0 .. 50 + width * height / 50 {
+        let (w, h) = (3 + rng.below(max_side - 2), 3 + rng.below(max_side - 2));
+        if w + 2 > width || h + 2 > height {
+            continue
+        }
+        let (x, y) = (1 + rng.below(width - w - 1), 1 + rng.below(height - h - 1));
+        // Rooms keep at least one wall between each other.
+        let overlaps = rooms.iter()
+            .any(|&(rx, ry, rw, rh)| x <= rx + rw && rx <= x + w && y <= ry + rh && ry <= y + h);
+        if overlaps {
+            continue
+        }
+        carve(&mut map, (x, y), (x + w - 1, y + h - 1));
+        if let Some (&(px, py, pw, ph)) = rooms.last() {
+            let (from, to) = ((px + pw / 2, py + ph / 2), (x + w / 2, y + h / 2));
+            let corner = if rng.below(2) == 0 { (to.0, from.1) } else { (from.0, to.1) };
+            carve(&mut map, from, corner);
+            carve(&mut map, corner, to);
+        }
+        rooms.push((x, y, w, h))
+    }
+    place_endpoints(&mut map, &mut rng);
+    map
+}
+
+/// Make all fields of the rectangle spanned by the two corners passable.
+fn carve(map: &mut Map, (x1, y1): Position, (x2, y2): Position) {
+    for y in y1.min(y2) ..= y1.max(y2) {
+        for x in x1.min(x2) ..= x1.max(x2) {
+            map[(x, y)] = Field::Passable
+        }
+    }
+}
+
+/// Organic caves grown by a cellular automaton.
+/// Only the largest cave is kept open.
+pub fn caves(width: usize, height: usize, seed: u64) -> Map {
+    let mut rng = Rng::new(seed);
+    let border = |(x, y): Position| x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+    let mut walls: Vec<bool> = (0 .. width * height)
+        .map(|i| border((i % width, i / width)) || rng.chance(0.45))
+        .collect();
+    for _ in 0 .. 5 {
+        walls = (0 .. width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let neighbouring_walls = (-1isize ..= 1)
+                    .flat_map(|dy| (-1isize ..= 1).map(move |dx| (dx, dy)))
+                    .filter(|&d| d != (0, 0))
+                    .filter(|&(dx, dy)| {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize
+                            || walls[ny as usize * width + nx as usize]
+                    })
+                    .count();
+                border((x, y)) || neighbouring_walls >= 5
+            })
+            .collect();
+    }
+    let fields = walls.into_iter()
+        .map(|wall| if wall { Field::Impassable } else { Field::Passable })
+        .collect();
+    let mut map = Map::new(width, height, fields);
+    let mut cave = vec![false; width * height];
+    for &(x, y) in largest_region(&map).iter() {
+        cave[y * width + x] = true
+    }
+    for (i, field) in map.fields.iter_mut().enumerate() {
+        if !cave[i] {
+            *field = Field::Impassable
+        }
+    }
+    place_endpoints(&mut map, &mut rng);
+    map
+}
+
+/// Obstacles scattered independently, each field being impassable with the given probability.
+pub fn obstacles(width: usize, height: usize, density: f64, seed: u64) -> Map {
+    let mut rng = Rng::new(seed);
+    let fields = (0 .. width * height)
+        .map(|_| if rng.chance(density) { Field::Impassable } else { Field::Passable })
+        .collect();
+    let mut map = Map::new(width, height, fields);
+    place_endpoints(&mut map, &mut rng);
+    map
+}
+
+/// Passable terrain of smoothly varying weights, made from value noise.
+pub fn terrain(width: usize, height: usize, seed: u64) -> Map {
+    let mut rng = Rng::new(seed);
+    let mut noise = vec![0.0; width * height];
+    // Two octaves: hills spanning about an eighth of the map with smaller bumps on top.
+    let coarse = (width.max(height) / 8).max(4);
+    for &(scale, amplitude) in &[(coarse, 0.7), ((coarse / 4).max(2), 0.3)] {
+        let (cols, rows) = (width / scale + 2, height / scale + 2);
+        let lattice: Vec<f64> = (0 .. cols * rows).map(|_| rng.unit()).collect();
+        for (i, value) in noise.iter_mut().enumerate() {
+            let (x, y) = ((i % width) as f64 / scale as f64, (i / width) as f64 / scale as f64);
+            let (cx, cy) = (x.floor() as usize, y.floor() as usize);
+            let (tx, ty) = (smoothstep(x.fract()), smoothstep(y.fract()));
+            let at = |c: usize, r: usize| lattice[r * cols + c];
+            let top = at(cx, cy) + (at(cx + 1, cy) - at(cx, cy)) * tx;
+            let bottom = at(cx, cy + 1) + (at(cx + 1, cy + 1) - at(cx, cy + 1)) * tx;
+            *value += amplitude * (top + (bottom - top) * ty)
+        }
+    }
+    let fields = noise.into_iter()
+        .map(|v| Field::Weighted((1 + (v * MAX_WEIGHT as f64) as u8).min(MAX_WEIGHT)))
+        .collect();
+    let mut map = Map::new(width, height, fields);
+    place_endpoints(&mut map, &mut rng);
+    map
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Put the start on a random passable field and the goal on the field
+/// farthest from it in 4-connected moves.
+fn place_endpoints(map: &mut Map, rng: &mut Rng) {
+    // Within the largest region, so that the start isn't walled in.
+    let region = largest_region(map);
+    if region.is_empty() {
+        return
+    }
+    let start = region[rng.below(region.len())];
+    let goal = *flood(map, start).last().unwrap_or(&start);
+    map[start] = Field::Start;
+    if goal != start {
+        map[goal] = Field::Goal
+    }
+}
+
+/// The largest set of 4-connected passable fields.
+fn largest_region(map: &Map) -> Vec<Position> {
+    let mut largest: Vec<Position> = vec![];
+    let mut seen = vec![false; map.width * map.height];
+    for pos in map.positions() {
+        if map[pos].is_passable() && !seen[pos.1 * map.width + pos.0] {
+            let region = flood(map, pos);
+            for &(x, y) in region.iter() {
+                seen[y * map.width + x] = true
+            }
+            if region.len() > largest.len() {
+                largest = region
+            }
+        }
+    }
+    largest
+}
+
+/// Passable fields 4-connected to `from`, in order of distance from it.
+fn flood(map: &Map, from: Position) -> Vec<Position> {
+    let mut seen = vec![false; map.width * map.height];
+    let mut queue = VecDeque::new();
+    let mut region = vec![];
+    seen[from.1 * map.width + from.0] = true;
+    queue.push_back(from);
+    while let Some ((x, y)) = queue.pop_front() {
+        region.push((x, y));
+        let candidates = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+        for &(nx, ny) in candidates.iter() {
+            if nx < map.width && ny < map.height && !seen[ny * map.width + nx]
+                && map[(nx, ny)].is_passable() {
+                seen[ny * map.width + nx] = true;
+                queue.push_back((nx, ny))
+            }
+        }
+    }
+    region
+}
+
+/// SplitMix64; a fixed algorithm keeps generated maps stable across versions and platforms.
+struct Rng(u64);
+
+impl Rng {
+
+    fn new(seed: u64) -> Rng { Rng(seed) }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0 .. n`; `n` must be positive.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1 .. items.len()).rev() {
+            items.swap(i, self.below(i + 1))
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::graph::GraphSearch;
+
+    fn assert_solvable(map: &Map) {
+        let mut search = crate::bfs(map.clone());
+        assert!(search.run(None).is_ok(), "goal unreachable on generated map");
+    }
+
+    #[test]
+    fn mazes_are_perfect_and_solvable() {
+        for &algorithm in &[MazeAlgorithm::RecursiveBacktracker,
+                            MazeAlgorithm::Prim,
+                            MazeAlgorithm::Kruskal] {
+            let map = maze(21, 15, algorithm, 7);
+            let cells = 10 * 7;
+            // Cells plus exactly one passage short of a cycle between them.
+            let open = map.fields.iter().filter(|f| f.is_passable()).count();
+            assert_eq!(2 * cells - 1, open, "{:?}", algorithm);
+            assert_solvable(&map);
+        }
+    }
+
+    #[test]
+    fn generators_are_reproducible_and_solvable() {
+        let generators: Vec<Box<dyn Fn(u64) -> Map>> = vec![
+            Box::new(|seed| dungeon(60, 40, seed)),
+            Box::new(|seed| caves(60, 40, seed)),
+            Box::new(|seed| obstacles(60, 40, 0.3, seed)),
+            Box::new(|seed| terrain(60, 40, seed))
+        ];
+        for generate in generators.iter() {
+            let map = generate(42);
+            assert!(map == generate(42));
+            assert!(map != generate(43));
+            assert_eq!(1, map.start().len());
+            assert_eq!(1, map.goals().len());
+            assert_solvable(&map);
+        }
+    }
+
+    #[test]
+    fn every_seed_gets_a_start_and_a_goal() {
+        for seed in 0 .. 300 {
+            for &density in &[0.3, 0.5] {
+                let map = obstacles(24, 18, density, seed);
+                assert_eq!(1, map.start().len(), "seed {} density {}", seed, density);
+                assert_eq!(1, map.goals().len(), "seed {} density {}", seed, density);
+            }
+            let map = caves(30, 20, seed);
+            assert_eq!((1, 1), (map.start().len(), map.goals().len()), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn obstacle_density_is_respected() {
+        let map = obstacles(100, 100, 0.25, 1);
+        let blocked = map.fields.iter().filter(|f| !f.is_passable()).count();
+        assert!((2300 .. 2700).contains(&blocked), "{} blocked fields", blocked);
+    }
+
+}
@@ -0,0 +1,282 @@
+//! Colour conventions of map images.
+//!
+//! A legend tells which colours stand for which fields when decoding a map
+//! and which colours to draw fields and search progress with.
+//! It can be loaded from a small config file:
+//!
+//! ```text
+//! # fields take one or more colours, each with an optional tolerance;
+//! # the first colour of a field is used for drawing it
+//! start = #00ff00 ~ 32
+//! goal = #ff0000 ~ 32, #c00000
+//! impassable = #0000ff ~ 32
+//! passable = #000000
+//! weight 4 = #806040
+//! # search progress
+//! visited = #5a5a5a
+//! frontier = #ff0000
+//! path = #ffffff
+//! # the half of a bidirectional search growing from the goals
+//! backward-visited = #6060a0
+//! backward-frontier = #ffa000
+//! # pixels matching none of the colours
+//! unknown = passable
+//! # whether unmatched grey pixels encode terrain weight
+//! grey-weights = yes
+//! transparent = impassable
+//! ```
+//!
+//! Fields which the file doesn't mention keep their default colours.
+
+use super::png::{ color_to_weight, weight_to_color, ColorRGB8, Transparency,
+                  BLACK, BLUE, GRAY, GREEN, RED, WHITE };
+use super::{ Field, MAX_WEIGHT };
+use super::super::Error;
+use crate::graph::NodeState;
+use std::fs;
+
+/// A colour and the colours close enough to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorRange {
+    pub color: ColorRGB8,
+    /// Largest difference in any channel of colours in the range.
+    pub tolerance: u8
+}
+
+impl ColorRange {
+
+    pub fn exact(color: ColorRGB8) -> ColorRange {
+        ColorRange { color, tolerance: 0 }
+    }
+
+    pub fn contains(&self, (r,g,b): ColorRGB8) -> bool {
+        let (cr, cg, cb) = self.color;
+        r.abs_diff(cr) <= self.tolerance
+            && g.abs_diff(cg) <= self.tolerance
+            && b.abs_diff(cb) <= self.tolerance
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Legend {
+    /// Colours of fields, tried in order when decoding.
+    /// The first colour of a field is the one it's drawn with.
+    pub fields: Vec<(Field, ColorRange)>,
+    pub visited: ColorRGB8,
+    pub frontier: ColorRGB8,
+    pub path: ColorRGB8,
+    pub backward_visited: ColorRGB8,
+    pub backward_frontier: ColorRGB8,
+    /// Field of pixels matching none of the colours.
+    pub unknown: Field,
+    /// Whether grey pixels matching none of the colours encode terrain weight,
+    /// from dark grey costing 1 to light grey costing `MAX_WEIGHT`;
+    /// greys off the ramp and the colours of search progress are left to `unknown`.
+    pub grey_weights: bool,
+    pub transparent: Transparency
+}
+
+impl Default for Legend {
+    fn default() -> Legend {
+        Legend {
+            fields: vec![(Field::Goal, ColorRange::exact(RED)),
+                         (Field::Start, ColorRange::exact(GREEN)),
+                         (Field::Impassable, ColorRange::exact(BLUE)),
+                         (Field::Passable, ColorRange::exact(BLACK))],
+            visited: GRAY,
+            frontier: RED,
+            path: WHITE,
+            backward_visited: (96, 96, 160),
+            backward_frontier: (255, 160, 0),
+            unknown: Field::Passable,
+            grey_weights: true,
+            transparent: Transparency::default()
+        }
+    }
+}
+
+impl Legend {
+
+    pub fn load(source: &str) -> Result<Legend, Error> {
+        Legend::parse(&fs::read_to_string(source)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Legend, Error> {
+        let mut legend = Legend::default();
+        let mut fields = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let malformed = |reason: &str| Error::Malformed(format!("line {}: {}", i + 1, reason));
+            let (key, value) = match line.find('=') {
+                Some (at) => (line[.. at].trim(), line[at + 1 ..].trim()),
+                None => return Err (malformed("expected KEY = VALUE"))
+            };
+            match key {
+                "visited" => legend.visited = parse_color(value).map_err(|e| malformed(&e))?,
+                "frontier" => legend.frontier = parse_color(value).map_err(|e| malformed(&e))?,
+                "path" => legend.path = parse_color(value).map_err(|e| malformed(&e))?,
+                "backward-visited" =>
+                    legend.backward_visited = parse_color(value).map_err(|e| malformed(&e))?,
+                "backward-frontier" =>
+                    legend.backward_frontier = parse_color(value).map_err(|e| malformed(&e))?,
+                "unknown" => legend.unknown = parse_field(value).map_err(|e| malformed(&e))?,
+                "grey-weights" => legend.grey_weights = match value {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err (malformed("expected yes or no"))
+                },
+                "transparent" => legend.transparent = match value {
+                    "impassable" => Transparency::Impassable,
+                    "passable" => Transparency::Passable,
+                    "ignore" => Transparency::Ignore,
+                    _ => return Err (malformed("expected impassable, passable or ignore"))
+                },
+                key => {
+                    let field = parse_field(key).map_err(|e| malformed(&e))?;
+                    for range in value.split(',') {
+                        fields.push((field, parse_range(range.trim()).map_err(|e| malformed(&e))?))
+                    }
+                }
+            }
+        }
+        let defaults: Vec<_> = legend.fields.into_iter()
+            .filter(|(field, _)| !fields.iter().any(|(f, _)| f == field))
+            .collect();
+        fields.extend(defaults);
+        legend.fields = fields;
+        Ok (legend)
+    }
+
+    /// Field of a pixel of the given colour.
+    pub fn field(&self, color: ColorRGB8) -> Field {
+        match self.fields.iter().find(|(_, range)| range.contains(color)) {
+            Some (&(field, _)) => field,
+            None => match color {
+                (r,g,b) if self.grey_weights && r == g && g == b && !self.is_progress(color) =>
+                    color_to_weight(color).map_or(self.unknown, Field::Weighted),
+                _ => self.unknown
+            }
+        }
+    }
+
+    /// Whether search progress is drawn with the colour, so it's no terrain weight.
+    fn is_progress(&self, color: ColorRGB8) -> bool {
+        [self.visited, self.frontier, self.path, self.backward_visited, self.backward_frontier]
+            .contains(&color)
+    }
+
+    /// Colour to draw the field with.
+    pub fn color(&self, field: Field) -> ColorRGB8 {
+        if let Some ((_, range)) = self.fields.iter().find(|(f, _)| *f == field) {
+            return range.color
+        }
+        match field {
+            Field::Weighted(w) if self.grey_weights => weight_to_color(w),
+            Field::Visited => self.visited,
+            Field::Frontier => self.frontier,
+            Field::Path | Field::Current => self.path,
+            Field::Impassable => BLUE,
+            Field::Passable => BLACK,
+            _ => self.color(Field::Passable)
+        }
+    }
+
+    /// Colour to draw a node of a search in progress with.
+    pub fn node_color(&self, state: NodeState) -> ColorRGB8 {
+        match state {
+            NodeState::Visited => self.visited,
+            NodeState::Frontier => self.frontier,
+            NodeState::Path => self.path,
+            NodeState::BackwardVisited => self.backward_visited,
+            NodeState::BackwardFrontier => self.backward_frontier
+        }
+    }
+
+}
+
+fn parse_field(name: &str) -> Result<Field, String> {
+    let mut words = name.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some ("start"), None, None) => Ok (Field::Start),
+        (Some ("goal"), None, None) => Ok (Field::Goal),
+        (Some ("passable"), None, None) => Ok (Field::Passable),
+        (Some ("impassable"), None, None) => Ok (Field::Impassable),
+        (Some ("weight"), Some (w), None) => match w.parse::<u8>() {
+            Ok (w) if (1 ..= MAX_WEIGHT).contains(&w) => Ok (Field::Weighted(w)),
+            _ => Err (format!("weight must be between 1 and {}", MAX_WEIGHT))
+        },
+        _ => Err (format!("unknown field: {}", name))
+    }
+}
+
+/// Parse `#rrggbb`, optionally followed by `~ tolerance`.
+fn parse_range(text: &str) -> Result<ColorRange, String> {
+    let mut parts = text.splitn(2, '~');
+    let color = parse_color(parts.next().unwrap_or("").trim())?;
+    let tolerance = match parts.next() {
+        Some (t) => t.trim().parse().map_err(|_| format!("invalid tolerance: {}", t.trim()))?,
+        None => 0
+    };
+    Ok (ColorRange { color, tolerance })
+}
+
+fn parse_color(text: &str) -> Result<ColorRGB8, String> {
+    let invalid = || format!("expected a #rrggbb colour, got {}", text);
+    if !text.starts_with('#') || text.len() != 7 {
+        return Err (invalid())
+    }
+    let channel = |i: usize| text.get(i .. i + 2)
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        .ok_or_else(invalid);
+    Ok ((channel(1)?, channel(3)?, channel(5)?))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn default_legend_decodes_like_before() {
+        let legend = Legend::default();
+        assert_eq!(Field::Goal, legend.field(RED));
+        assert_eq!(Field::Start, legend.field(GREEN));
+        assert_eq!(Field::Impassable, legend.field(BLUE));
+        assert_eq!(Field::Passable, legend.field(BLACK));
+        assert_eq!(Field::Passable, legend.field(WHITE));
+        assert_eq!(Field::Weighted(MAX_WEIGHT), legend.field(weight_to_color(MAX_WEIGHT)));
+        assert_eq!(Field::Passable, legend.field((10, 200, 30)));
+        assert_eq!(weight_to_color(3), legend.color(Field::Weighted(3)));
+    }
+
+    #[test]
+    fn config_overrides_colours_with_tolerance() {
+        let legend = Legend::parse("# team B\n\
+                                    impassable = #ff0000 ~ 20, #800000\n\
+                                    goal = #ffff00\n\
+                                    weight 4 = #806040\n\
+                                    visited = #5a5a5a\n\
+                                    unknown = impassable\n").unwrap();
+        assert_eq!(Field::Impassable, legend.field((240, 10, 5)));
+        assert_eq!(Field::Impassable, legend.field((128, 0, 0)));
+        assert_eq!(Field::Goal, legend.field((255, 255, 0)));
+        assert_eq!(Field::Start, legend.field(GREEN));
+        assert_eq!(Field::Weighted(4), legend.field((128, 96, 64)));
+        assert_eq!(Field::Impassable, legend.field((10, 200, 30)));
+        assert_eq!(RED, legend.color(Field::Impassable));
+        assert_eq!((90, 90, 90), legend.node_color(NodeState::Visited));
+    }
+
+    #[test]
+    fn reports_line_of_malformed_config() {
+        match Legend::parse("start = #00ff00\ngoal = red\n") {
+            Err (Error::Malformed(reason)) => assert!(reason.starts_with("line 2")),
+            _ => panic!("expected Malformed")
+        }
+    }
+
+}
@@ -1,23 +1,165 @@
+use std::fmt;
 use std::ops::{ Index, IndexMut };
+use super::{ Error, Path };
 
+pub mod ascii;
+pub mod generate;
+pub mod legend;
+pub mod movingai;
 pub mod png;
 
 pub type Position = (usize, usize);
 
+/// Highest traversal cost a `Field::Weighted` terrain cell is expected to have.
+pub const MAX_WEIGHT: u8 = 10;
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Map {
     pub width: usize,
     pub height: usize,
-    pub fields: Vec<Field>
+    pub fields: Vec<Field>,
+    pub rules: MovementRules
+}
+
+/// Which moves between neighbouring fields are allowed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MovementRules {
+    pub connectivity: Connectivity,
+    pub shape: WorldShape
+}
+
+/// What happens to moves leaving the map through one of its edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WorldShape {
+    /// Such moves are not allowed.
+    #[default]
+    Bounded,
+    /// Left and right edges are joined.
+    WrapHorizontally,
+    /// Top and bottom edges are joined.
+    WrapVertically,
+    /// Both pairs of opposite edges are joined.
+    Torus
+}
+
+impl WorldShape {
+
+    /// Look a shape up by its command line name.
+    pub fn by_name(name: &str) -> Option<WorldShape> {
+        match name {
+            "bounded" => Some (WorldShape::Bounded),
+            "wrap-horizontally" => Some (WorldShape::WrapHorizontally),
+            "wrap-vertically" => Some (WorldShape::WrapVertically),
+            "torus" => Some (WorldShape::Torus),
+            _ => None
+        }
+    }
+
+    pub fn wraps_horizontally(&self) -> bool {
+        match *self {
+            WorldShape::WrapHorizontally | WorldShape::Torus => true,
+            WorldShape::Bounded | WorldShape::WrapVertically => false
+        }
+    }
+
+    pub fn wraps_vertically(&self) -> bool {
+        match *self {
+            WorldShape::WrapVertically | WorldShape::Torus => true,
+            WorldShape::Bounded | WorldShape::WrapHorizontally => false
+        }
+    }
+
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    /// Horizontal and vertical moves only.
+    Four,
+    /// Any of the 8 moves, even diagonally between two impassable fields.
+    #[default]
+    Eight,
+    /// Diagonal moves are forbidden only if both fields they pass by are impassable,
+    /// i.e. it's not possible to squeeze through a diagonal gap.
+    EightNoSqueezing,
+    /// Diagonal moves are allowed only if both fields they pass by are passable,
+    /// i.e. it's not possible to cut corners of obstacles.
+    EightNoCornerCutting
+}
+
+impl Connectivity {
+
+    /// Look a connectivity up by its command line name.
+    pub fn by_name(name: &str) -> Option<Connectivity> {
+        match name {
+            "4" => Some (Connectivity::Four),
+            "8" => Some (Connectivity::Eight),
+            "8-no-squeezing" => Some (Connectivity::EightNoSqueezing),
+            "8-no-corner-cutting" => Some (Connectivity::EightNoCornerCutting),
+            _ => None
+        }
+    }
+
+    /// Whether a move by `(dx, dy)` is allowed, given whether the horizontally
+    /// and the vertically adjacent fields it passes by are passable.
+    pub fn allows(&self, (dx, dy): (isize, isize),
+                  horizontal_free: bool, vertical_free: bool) -> bool {
+        if dx == 0 || dy == 0 {
+            return true
+        }
+        match *self {
+            Connectivity::Four => false,
+            Connectivity::Eight => true,
+            Connectivity::EightNoSqueezing => horizontal_free || vertical_free,
+            Connectivity::EightNoCornerCutting => horizontal_free && vertical_free
+        }
+    }
+
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// Why a path can't be followed on a map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathViolation {
+    /// The path has no positions at all.
+    Empty,
+    /// The path doesn't begin at a start field.
+    NotAtStart(Position),
+    /// The path doesn't end at a goal field.
+    NotAtGoal(Position),
+    /// The position lies outside of the map.
+    OutOfBounds(Position),
+    /// The position is an impassable field.
+    Impassable(Position),
+    /// The move doesn't go to a neighbouring field.
+    NotAdjacent(Position, Position),
+    /// The diagonal move isn't allowed past the fields beside it.
+    CornerCut(Position, Position)
+}
+
+impl fmt::Display for PathViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathViolation::Empty => write!(f, "the path is empty"),
+            PathViolation::NotAtStart((x,y)) => write!(f, "({}, {}) is not a start field", x, y),
+            PathViolation::NotAtGoal((x,y)) => write!(f, "({}, {}) is not a goal field", x, y),
+            PathViolation::OutOfBounds((x,y)) => write!(f, "({}, {}) is out of map bounds", x, y),
+            PathViolation::Impassable((x,y)) => write!(f, "({}, {}) is impassable", x, y),
+            PathViolation::NotAdjacent((x1,y1), (x2,y2)) =>
+                write!(f, "({}, {}) is not next to ({}, {})", x2, y2, x1, y1),
+            PathViolation::CornerCut((x1,y1), (x2,y2)) =>
+                write!(f, "moving diagonally from ({}, {}) to ({}, {}) is not allowed", x1, y1, x2, y2)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Field {
     Start,
     Goal,
     Path,
     Current,
     Passable,
+    /// Passable terrain which costs the given weight to enter.
+    Weighted(u8),
     Impassable,
     Visited,
     Frontier
@@ -30,10 +172,23 @@ impl Field {
             _ => true
         }
     }
+
+    /// Cost of entering the field; infinite if the field is impassable.
+    pub fn cost(&self) -> f64 {
+        match *self {
+            Field::Impassable => f64::INFINITY,
+            Field::Weighted(weight) => weight as f64,
+            _ => 1.0
+        }
+    }
 }
 
 impl Map {
 
+    pub fn new(width: usize, height: usize, fields: Vec<Field>) -> Map {
+        Map { width, height, fields, rules: MovementRules::default() }
+    }
+
     pub fn start(&self) -> Vec<Position> {
         self.positions()
             .filter(|&(x,y)| match self[(x,y)] {
@@ -51,6 +206,29 @@ impl Map {
             }).collect()
     }
 
+    /// Replace all start fields with the given positions.
+    pub fn set_start(&mut self, start: &[Position]) -> Result<(), Error> {
+        self.replace_fields(Field::Start, start)
+    }
+
+    /// Replace all goal fields with the given positions.
+    pub fn set_goals(&mut self, goals: &[Position]) -> Result<(), Error> {
+        self.replace_fields(Field::Goal, goals)
+    }
+
+    fn replace_fields(&mut self, field: Field, positions: &[Position]) -> Result<(), Error> {
+        if let Some (&pos) = positions.iter().find(|&&(x,y)| x >= self.width || y >= self.height) {
+            return Err (Error::OutOfBounds(pos))
+        }
+        for f in self.fields.iter_mut().filter(|f| **f == field) {
+            *f = Field::Passable
+        }
+        for &pos in positions {
+            self[pos] = field
+        }
+        Ok (())
+    }
+
     pub fn positions(&self) -> MapPositions {
         MapPositions { x: 0, y: 0, width: self.width,
                        size: self.width * self.height }
@@ -60,6 +238,93 @@ impl Map {
         (self.width as isize, self.height as isize)
     }
 
+    /// Horizontal and vertical distance between two positions,
+    /// going across the joined edges if the world shape allows it.
+    pub fn delta(&self, (x1,y1): Position, (x2,y2): Position) -> (usize, usize) {
+        let (dx, dy) = (x1.abs_diff(x2), y1.abs_diff(y2));
+        (if self.rules.shape.wraps_horizontally() { dx.min(self.width - dx) } else { dx },
+         if self.rules.shape.wraps_vertically() { dy.min(self.height - dy) } else { dy })
+    }
+
+    /// Check that a path leads from a start field to a goal field in moves allowed by `rules`,
+    /// reporting the index of the first position where it doesn't.
+    ///
+    /// Searches return paths from the goal back to the start, see `path::normalise`.
+    pub fn validate_path(&self, path: &Path, rules: &MovementRules) -> Result<(), Error> {
+        let invalid = |step, violation| Err (Error::InvalidPath(step, violation));
+        if path.is_empty() {
+            return invalid(0, PathViolation::Empty)
+        }
+        for (step, &pos) in path.iter().enumerate() {
+            if pos.0 >= self.width || pos.1 >= self.height {
+                return invalid(step, PathViolation::OutOfBounds(pos))
+            }
+            if step == 0 && self[pos] != Field::Start {
+                return invalid(step, PathViolation::NotAtStart(pos))
+            }
+            if !self[pos].is_passable() {
+                return invalid(step, PathViolation::Impassable(pos))
+            }
+            if step == 0 {
+                continue
+            }
+            let from = path[step - 1];
+            let dx = displacement(from.0, pos.0, self.width, rules.shape.wraps_horizontally());
+            let dy = displacement(from.1, pos.1, self.height, rules.shape.wraps_vertically());
+            if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) {
+                return invalid(step, PathViolation::NotAdjacent(from, pos))
+            }
+            if !rules.connectivity.allows((dx, dy), self[(pos.0, from.1)].is_passable(),
+                                                    self[(from.0, pos.1)].is_passable()) {
+                return invalid(step, PathViolation::CornerCut(from, pos))
+            }
+        }
+        let last = path[path.len() - 1];
+        if self[last] != Field::Goal {
+            return invalid(path.len() - 1, PathViolation::NotAtGoal(last))
+        }
+        Ok (())
+    }
+
+    /// Fields crossed by the straight line between the centres of two fields, in order.
+    ///
+    /// Where the line passes exactly through a corner, only the diagonal field is included,
+    /// so that consecutive fields are always neighbours. The line doesn't wrap around edges.
+    pub fn line(&self, (x0,y0): Position, (x1,y1): Position) -> Vec<Position> {
+        let (dx, dy) = (x0.abs_diff(x1) as isize, y0.abs_diff(y1) as isize);
+        let (sx, sy) = ((x1 as isize - x0 as isize).signum(), (y1 as isize - y0 as isize).signum());
+        let (mut x, mut y) = (x0 as isize, y0 as isize);
+        let (mut ix, mut iy) = (0, 0);
+        let mut line = vec![(x0, y0)];
+        while ix < dx || iy < dy {
+            // Compare where the line next crosses a vertical and a horizontal field border.
+            let decision = (1 + 2 * ix) * dy - (1 + 2 * iy) * dx;
+            if decision <= 0 {
+                x += sx;
+                ix += 1;
+            }
+            if decision >= 0 {
+                y += sy;
+                iy += 1;
+            }
+            line.push((x as usize, y as usize));
+        }
+        line
+    }
+
+    /// Whether the straight line between the centres of two fields crosses only passable fields,
+    /// and passes corners only where the connectivity allows moving diagonally past them.
+    pub fn line_of_sight(&self, from: Position, to: Position) -> bool {
+        let line = self.line(from, to);
+        line.iter().all(|&pos| self[pos].is_passable())
+            && line.windows(2).all(|step| {
+                let ((ax, ay), (bx, by)) = (step[0], step[1]);
+                let d = (bx as isize - ax as isize, by as isize - ay as isize);
+                self.rules.connectivity.allows(d, self[(bx, ay)].is_passable(),
+                                                  self[(ax, by)].is_passable())
+            })
+    }
+
 }
 
 #[derive(Clone, Copy)]
@@ -96,18 +361,87 @@ impl IndexMut<Position> for Map {
     }
 }
 
+/// Signed distance from one coordinate to another, the short way around if the axis wraps.
+fn displacement(from: usize, to: usize, size: usize, wraps: bool) -> isize {
+    let d = to as isize - from as isize;
+    if wraps && d.abs() == size as isize - 1 && size > 2 { -d.signum() } else { d }
+}
+
 #[inline]
 pub fn index((x,y): (usize,usize), width: usize) -> usize { y * width + x }
 
 #[test]
 fn test_map_positions() {
-    let m1 = Map { width: 1, height: 1, fields: vec![] };
+    let m1 = Map::new(1, 1, vec![]);
     assert_eq!(vec![(0,0)], m1.positions().collect::<Vec<Position>>());
-    let m2 = Map { width: 3, height: 2, fields: vec![] };
+    let m2 = Map::new(3, 2, vec![]);
     assert_eq!(vec![(0,0),(1,0),(2,0),
                     (0,1),(1,1),(2,1)], m2.positions().collect::<Vec<Position>>());
-    let m3 = Map { width: 2, height: 3, fields: vec![] };
+    let m3 = Map::new(2, 3, vec![]);
     assert_eq!(vec![(0,0),(1,0),
                     (0,1),(1,1),
                     (0,2),(1,2)], m3.positions().collect::<Vec<Position>>());
 }
+
+#[test]
+fn test_map_delta() {
+    let mut map = Map::new(10, 6, vec![]);
+    assert_eq!((8,4), map.delta((1,1), (9,5)));
+    map.rules.shape = WorldShape::WrapHorizontally;
+    assert_eq!((2,4), map.delta((1,1), (9,5)));
+    map.rules.shape = WorldShape::Torus;
+    assert_eq!((2,2), map.delta((1,1), (9,5)));
+    assert_eq!((5,3), map.delta((0,0), (5,3)));
+}
+
+#[test]
+fn test_line_of_sight() {
+    let map = ascii::parse("S..#\n....\n.#.G\n", &ascii::Glyphs::default()).unwrap();
+    assert_eq!(vec![(0,0), (1,0), (1,1), (2,1), (2,2), (3,2)], map.line((0,0), (3,2)));
+    assert_eq!(vec![(3,2), (2,2), (2,1), (1,1), (1,0), (0,0)], map.line((3,2), (0,0)));
+    assert!(map.line_of_sight((0,0), (3,2)));
+    assert!(!map.line_of_sight((0,0), (3,0)));
+    assert!(!map.line_of_sight((0,1), (2,2)));
+    let mut map = ascii::parse("S#\n#G\n", &ascii::Glyphs::default()).unwrap();
+    assert!(map.line_of_sight((0,0), (1,1)));
+    map.rules.connectivity = Connectivity::EightNoSqueezing;
+    assert!(!map.line_of_sight((0,0), (1,1)));
+}
+
+#[test]
+fn test_validate_path() {
+    let mut map = ascii::parse("S#.\n.#.\n..G\n", &ascii::Glyphs::default()).unwrap();
+    let rules = map.rules;
+    assert!(map.validate_path(&vec![(0,0), (0,1), (1,2), (2,2)], &rules).is_ok());
+    let invalid = |map: &Map, path: Path, rules: &MovementRules| match map.validate_path(&path, rules) {
+        Err (Error::InvalidPath(step, violation)) => (step, violation),
+        result => panic!("{:?} accepted: {:?}", path, result)
+    };
+    assert_eq!((0, PathViolation::Empty), invalid(&map, vec![], &rules));
+    assert_eq!((0, PathViolation::NotAtStart((0,1))), invalid(&map, vec![(0,1), (1,2), (2,2)], &rules));
+    assert_eq!((1, PathViolation::Impassable((1,0))), invalid(&map, vec![(0,0), (1,0), (2,1), (2,2)], &rules));
+    assert_eq!((1, PathViolation::NotAdjacent((0,0), (0,2))), invalid(&map, vec![(0,0), (0,2), (1,2), (2,2)], &rules));
+    assert_eq!((2, PathViolation::OutOfBounds((0,3))), invalid(&map, vec![(0,0), (0,1), (0,3)], &rules));
+    assert_eq!((2, PathViolation::NotAtGoal((1,2))), invalid(&map, vec![(0,0), (0,1), (1,2)], &rules));
+    let strict = MovementRules { connectivity: Connectivity::EightNoCornerCutting, ..rules };
+    assert_eq!((2, PathViolation::CornerCut((0,1), (1,2))),
+               invalid(&map, vec![(0,0), (0,1), (1,2), (2,2)], &strict));
+    assert!(map.validate_path(&vec![(0,0), (0,1), (0,2), (1,2), (2,2)], &strict).is_ok());
+    map[(0,1)] = Field::Impassable;
+    let torus = MovementRules { shape: WorldShape::Torus, ..rules };
+    assert!(map.validate_path(&vec![(0,0), (0,2), (1,2), (2,2)], &torus).is_ok());
+    assert!(map.validate_path(&vec![(0,0), (2,2)], &torus).is_ok());
+}
+
+#[test]
+fn test_set_endpoints() {
+    let mut map = Map::new(3, 1, vec![Field::Start, Field::Passable, Field::Goal]);
+    map.set_start(&[(1,0)]).unwrap();
+    map.set_goals(&[(0,0), (2,0)]).unwrap();
+    assert_eq!(vec![(1,0)], map.start());
+    assert_eq!(vec![(0,0), (2,0)], map.goals());
+    match map.set_goals(&[(3,0)]) {
+        Err (Error::OutOfBounds((3,0))) => (),
+        _ => panic!("expected OutOfBounds")
+    }
+}
@@ -0,0 +1,214 @@
+//! MovingAI benchmark formats: `.map` grids and `.scen` scenario lists,
+//! as described on https://movingai.com/benchmarks/formats.html.
+
+use super::{ Connectivity, Field, Map, Position };
+use super::super::Error;
+use std::fs;
+
+/// A single search problem of a scenario file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    /// Scenarios are grouped into buckets of similar optimal length.
+    pub bucket: usize,
+    /// Map file the scenario refers to, usually relative to the scenario file.
+    pub map: String,
+    pub width: usize,
+    pub height: usize,
+    pub start: Position,
+    pub goal: Position,
+    /// Cost of the shortest path, with diagonal moves costing √2.
+    pub optimal_length: f64
+}
+
+impl Scenario {
+
+    /// Check that the scenario was made for a map of the given size.
+    pub fn check_map(&self, map: &Map) -> Result<(), Error> {
+        if (self.width, self.height) != (map.width, map.height) {
+            return Err (malformed(&format!("scenario for a {}x{} map, but {} is {}x{}",
+                                           self.width, self.height, self.map,
+                                           map.width, map.height)))
+        }
+        Ok (())
+    }
+
+}
+
+pub fn load(source: &str) -> Result<Map, Error> {
+    parse(&fs::read_to_string(source)?)
+}
+
+/// Parse an octile map.
+///
+/// `.`, `G` and `S` are passable, while `@`, `O`, `T` and `W` are not.
+/// Water is reachable only from water in the original rules,
+/// so it's treated as an obstacle, just like the benchmark results do for land units.
+/// The map gets the benchmark movement rules: diagonal moves can't cut corners.
+pub fn parse(text: &str) -> Result<Map, Error> {
+    let mut lines = text.lines().map(|line| line.trim_end());
+    let (mut width, mut height) = (None, None);
+    loop {
+        let line = lines.next()
+            .ok_or_else(|| malformed("missing map section"))?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some ("map"), None) => break,
+            (Some ("type"), Some ("octile")) => (),
+            (Some ("type"), Some (other)) => return Err (malformed(&format!("unsupported map type {}", other))),
+            (Some ("width"), Some (w)) => width = Some (parse_number(w)?),
+            (Some ("height"), Some (h)) => height = Some (parse_number(h)?),
+            _ => return Err (malformed(&format!("unexpected header line: {}", line)))
+        }
+    }
+    let width = width.ok_or_else(|| malformed("missing width"))?;
+    let height = height.ok_or_else(|| malformed("missing height"))?;
+    let mut fields = Vec::with_capacity(width * height);
+    for (y, line) in lines.take(height).enumerate() {
+        if line.chars().count() != width {
+            return Err (malformed(&format!("row {} is not {} fields wide", y, width)))
+        }
+        for glyph in line.chars() {
+            fields.push(glyph_to_field(glyph)?)
+        }
+    }
+    if fields.len() != width * height {
+        return Err (malformed(&format!("expected {} rows", height)))
+    }
+    let mut map = Map::new(width, height, fields);
+    map.rules.connectivity = Connectivity::EightNoCornerCutting;
+    Ok (map)
+}
+
+fn glyph_to_field(glyph: char) -> Result<Field, Error> {
+    match glyph {
+        '.' | 'G' | 'S' => Ok (Field::Passable),
+        '@' | 'O' | 'T' | 'W' => Ok (Field::Impassable),
+        other => Err (malformed(&format!("unknown terrain {:?}", other)))
+    }
+}
+
+pub fn save(map: &Map, dest: &str) -> Result<(), Error> {
+    Ok (fs::write(dest, write(map))?)
+}
+
+/// Render the map as an octile map.
+/// The format has no start, goal or weighted fields; all of them are written as passable.
+pub fn write(map: &Map) -> String {
+    let mut text = format!("type octile\nheight {}\nwidth {}\nmap\n", map.height, map.width);
+    for row in map.fields.chunks(map.width) {
+        text.extend(row.iter().map(|f| if f.is_passable() { '.' } else { '@' }));
+        text.push('\n');
+    }
+    text
+}
+
+pub fn load_scenarios(source: &str) -> Result<Vec<Scenario>, Error> {
+    parse_scenarios(&fs::read_to_string(source)?)
+}
+
+pub fn parse_scenarios(text: &str) -> Result<Vec<Scenario>, Error> {
+    text.lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty() && !line.starts_with("version"))
+        .map(parse_scenario)
+        .collect()
+}
+
+fn parse_scenario(line: &str) -> Result<Scenario, Error> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() != 9 {
+        return Err (malformed(&format!("expected 9 tab separated columns: {}", line)))
+    }
+    let n = |i: usize| parse_number(columns[i]);
+    Ok (Scenario {
+        bucket: n(0)?,
+        map: columns[1].to_string(),
+        width: n(2)?,
+        height: n(3)?,
+        start: (n(4)?, n(5)?),
+        goal: (n(6)?, n(7)?),
+        optimal_length: columns[8].parse()
+            .map_err(|_| malformed(&format!("not a number: {}", columns[8])))?
+    })
+}
+
+pub fn save_scenarios(scenarios: &[Scenario], dest: &str) -> Result<(), Error> {
+    Ok (fs::write(dest, write_scenarios(scenarios))?)
+}
+
+pub fn write_scenarios(scenarios: &[Scenario]) -> String {
+    let mut text = String::from("version 1\n");
+    for s in scenarios {
+        text.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.8}\n",
+                               s.bucket, s.map, s.width, s.height,
+                               s.start.0, s.start.1, s.goal.0, s.goal.1, s.optimal_length));
+    }
+    text
+}
+
+fn parse_number(text: &str) -> Result<usize, Error> {
+    text.parse().map_err(|_| malformed(&format!("not a number: {}", text)))
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Malformed(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::graph::GraphSearch;
+
+    const MAP: &str = "type octile\nheight 3\nwidth 4\nmap\n....\n.@T.\n....\n";
+    const SCEN: &str = "version 1\n0\tsmall.map\t4\t3\t0\t0\t3\t2\t5.00000000\n";
+
+    #[test]
+    fn map_round_trips() {
+        let map = parse(MAP).unwrap();
+        assert_eq!((4, 3), (map.width, map.height));
+        assert_eq!(Field::Impassable, map[(2,1)]);
+        assert_eq!(Connectivity::EightNoCornerCutting, map.rules.connectivity);
+        assert_eq!(MAP.replace('T', "@"), write(&map));
+    }
+
+    #[test]
+    fn rejects_rows_of_wrong_width() {
+        match parse("type octile\nheight 1\nwidth 3\nmap\n..\n") {
+            Err (Error::Malformed(_)) => (),
+            _ => panic!("expected Malformed")
+        }
+    }
+
+    #[test]
+    fn scenarios_round_trip() {
+        let scenarios = parse_scenarios(SCEN).unwrap();
+        assert_eq!(vec![Scenario { bucket: 0, map: "small.map".to_string(),
+                                   width: 4, height: 3, start: (0,0), goal: (3,2),
+                                   optimal_length: 5.0 }],
+                   scenarios);
+        assert_eq!(SCEN, write_scenarios(&scenarios));
+    }
+
+    #[test]
+    fn astar_matches_optimal_length() {
+        let mut map = parse(MAP).unwrap();
+        let scenario = &parse_scenarios(SCEN).unwrap()[0];
+        scenario.check_map(&map).unwrap();
+        map.set_start(&[scenario.start]).unwrap();
+        map.set_goals(&[scenario.goal]).unwrap();
+        let mut search = crate::astar_search(map);
+        search.run(None).unwrap();
+        assert!((search.stats().path_cost - scenario.optimal_length).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_scenarios_for_other_map_sizes() {
+        let map = parse("type octile\nheight 3\nwidth 5\nmap\n.....\n.....\n.....\n").unwrap();
+        match parse_scenarios(SCEN).unwrap()[0].check_map(&map) {
+            Err (Error::Malformed(reason)) => assert!(reason.contains("4x3")),
+            _ => panic!("expected Malformed")
+        }
+    }
+
+}
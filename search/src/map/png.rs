@@ -1,8 +1,8 @@
 extern crate png;
 
-use super::{ Field, Map, Position };
-use super::super::Search;
-use std::iter::repeat;
+use super::legend::Legend;
+use super::{ Field, Map, Position, MAX_WEIGHT };
+use super::super::{ Error, Search };
 
 pub type ColorRGB8 = (u8,u8,u8);
 
@@ -16,75 +16,157 @@ pub const BLUE : ColorRGB8 = (  0,  0,255);
 #[derive(Debug)]
 pub enum Pixels {
     RGB8(Vec<u8>),
-    //RGBA8(Vec<u8>)
+    RGBA8(Vec<u8>)
 }
 
+/// Colour type and bit depth of a PNG file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub color_type: png::ColorType,
+    pub bit_depth: png::BitDepth
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format { color_type: png::ColorType::RGB, bit_depth: png::BitDepth::Eight }
+    }
+}
+
+/// What transparent pixels of a map image stand for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transparency {
+    /// Only the colour of a pixel matters.
+    Ignore,
+    Passable,
+    #[default]
+    Impassable
+}
+
+/// Pixels less opaque than this are transparent.
+pub const ALPHA_THRESHOLD: u8 = 128;
+
 #[derive(Debug)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
     pub pixels: Pixels,
+    /// Format the image was read from or is going to be written in.
+    pub format: Format
 }
 
-pub fn load(source: &str) -> Map {
-    let image = load_image(source);
+pub fn load(source: &str) -> Result<Map, Error> {
+    load_with(source, &Legend::default()).map(|(map, _)| map)
+}
+
+/// Load a map along with the format of its image, so that results can be written the same way.
+pub fn load_with(source: &str, legend: &Legend) -> Result<(Map, Format), Error> {
+    let image = load_image(source)?;
     let w = image.width as usize;
     let h = image.height as usize;
-    let Pixels::RGB8(ref pixels) = image.pixels;
-    Map {
-        width: w,
-        height: h,
-        fields: pixels_to_fields(pixels, w, h, 3)
-    }
+    let fields = match image.pixels {
+        Pixels::RGB8(ref pixels) => pixels_to_fields(pixels, 3, legend),
+        Pixels::RGBA8(ref pixels) => pixels_to_fields(pixels, 4, legend)
+    };
+    Ok ((Map::new(w, h, fields), image.format))
 }
 
-pub fn load_image(source: &str) -> Image {
-    let decoder = png::Decoder::new(std::fs::File::open(source).unwrap());
-    let (info, mut reader) = decoder.read_info().unwrap();
+/// Load any PNG the decoder understands, normalised to 8-bit RGB, or RGBA if it has transparency.
+pub fn load_image(source: &str) -> Result<Image, Error> {
+    let mut decoder = png::Decoder::new(std::fs::File::open(source)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let format = Format { color_type: reader.info().color_type,
+                          bit_depth: reader.info().bit_depth };
     let mut buf = vec![0; info.buffer_size()];
-    reader.next_frame(&mut buf).unwrap();
-    Image {
+    reader.next_frame(&mut buf)?;
+    let pixels = match info.color_type {
+        png::ColorType::RGB => Pixels::RGB8(buf),
+        png::ColorType::RGBA => Pixels::RGBA8(buf),
+        png::ColorType::Grayscale =>
+            Pixels::RGB8(buf.iter().flat_map(|&v| vec![v, v, v]).collect()),
+        png::ColorType::GrayscaleAlpha =>
+            Pixels::RGBA8(buf.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect()),
+        png::ColorType::Indexed =>
+            return Err (Error::UnsupportedPng("palette was not expanded".to_string()))
+    };
+    Ok (Image {
         width: info.width,
         height: info.height,
-        pixels: Pixels::RGB8(buf)
+        pixels,
+        format
+    })
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(e: png::DecodingError) -> Error {
+        match e {
+            png::DecodingError::IoError(e) => Error::from(e),
+            e => Error::UnsupportedPng(e.to_string())
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Error {
+        match e {
+            png::EncodingError::IoError(e) => Error::from(e),
+            e => Error::UnsupportedPng(e.to_string())
+        }
     }
 }
 
 pub fn map_to_png(map: &Map) -> Image {
+    map_to_png_with(map, &Legend::default())
+}
+
+/// Draw the terrain of the map in the colours of the legend.
+/// Start and goal fields are drawn as passable; they belong to search results.
+pub fn map_to_png_with(map: &Map, legend: &Legend) -> Image {
     let mut pixels: Vec<u8> = Vec::with_capacity(3 * map.width * map.height);
     for f in map.fields.iter() {
-        // TODO: for now this is enough, but later let's get rid of collections
-        // in SearchResult and enable more clauses here;
-        // even better - extract to field_to_pixel
-        if let Field::Impassable = *f
-            { pixels.extend(vec![0u8, 0u8, 255u8].into_iter()) }
-        else
-            { pixels.extend(vec![0u8, 0u8, 0u8].into_iter()) }
+        let terrain = match *f {
+            Field::Impassable | Field::Weighted(_) => *f,
+            _ => Field::Passable
+        };
+        let (r,g,b) = legend.color(terrain);
+        pixels.extend(&[r, g, b])
     }
     Image { width: map.width as u32,
             height: map.height as u32,
-            pixels: Pixels::RGB8(pixels) }
+            pixels: Pixels::RGB8(pixels),
+            format: Format::default() }
 }
 
-fn pixels_to_fields(pixels: &Vec<u8>, width: usize, height: usize,
-                    bytes_per_pixel: usize) -> Vec<Field> {
-    let mut fields: Vec<Field> =
-        repeat(Field::Passable).take(width * height).collect();
-    for i in 0 .. width * height {
-        let j = i * bytes_per_pixel;
-        let color: ColorRGB8 = (pixels[j], pixels[j+1], pixels[j+2]);
-        fields[i] = pixel_to_field(color);
-    }
-    fields
+fn pixels_to_fields(pixels: &[u8], bytes_per_pixel: usize, legend: &Legend) -> Vec<Field> {
+    pixels.chunks(bytes_per_pixel)
+        .map(|p| {
+            let transparent = bytes_per_pixel == 4 && p[3] < ALPHA_THRESHOLD;
+            match legend.transparent {
+                Transparency::Passable if transparent => Field::Passable,
+                Transparency::Impassable if transparent => Field::Impassable,
+                _ => legend.field((p[0], p[1], p[2]))
+            }
+        })
+        .collect()
 }
 
-fn pixel_to_field((r,g,b): ColorRGB8) -> Field {
-    match (r,g,b) {
-        RED => Field::Goal,
-        GREEN => Field::Start,
-        BLUE => Field::Impassable,
-        _ => Field::Passable
-    }
+/// Grey level of weight 1; each further weight is `WEIGHT_STEP` lighter.
+/// The ramp stays clear of black, `GRAY` and `WHITE`, which draw fields and search progress.
+const WEIGHT_BASE: u8 = 20;
+const WEIGHT_STEP: u8 = 24;
+
+/// Grey levels encode terrain weight, from dark grey costing 1 to light grey costing `MAX_WEIGHT`.
+/// Levels further than a third of a step from the ramp encode no weight.
+pub fn color_to_weight((r,_,_): ColorRGB8) -> Option<u8> {
+    let offset = r.checked_sub(WEIGHT_BASE - WEIGHT_STEP / 3)?;
+    let (weight, rest) = (1 + offset / WEIGHT_STEP, offset % WEIGHT_STEP);
+    if weight <= MAX_WEIGHT && rest <= 2 * (WEIGHT_STEP / 3) { Some (weight) }
+    else { None }
+}
+
+pub fn weight_to_color(weight: u8) -> ColorRGB8 {
+    let level = WEIGHT_BASE + (weight.clamp(1, MAX_WEIGHT) - 1) * WEIGHT_STEP;
+    (level, level, level)
 }
 
 pub fn draw_points(points: &Vec<Position>, color: ColorRGB8,
@@ -93,43 +175,16 @@ pub fn draw_points(points: &Vec<Position>, color: ColorRGB8,
         { putpixel(*point, color, img) }
 }
 
-fn putpixel(pos: (usize,usize), color: ColorRGB8, img: &mut Image) {
-    let pixel_width: u8 = 3;
-    //match img.pixels {
-    //    Pixels::RGB8(_) => 3,
-    //    //Pixels::RGBA8(_) => 4,
-    //    _ => panic!("only RGB8 and RGBA8 modes are supported")
-    //};
+fn putpixel(pos: (usize,usize), (r,g,b): ColorRGB8, img: &mut Image) {
     match img.pixels {
-        //Pixels::RGB8(ref mut pixels) |
-        //Pixels::RGBA8(ref mut pixels) => {
         Pixels::RGB8(ref mut pixels) => {
-            for i in 0 .. pixel_width {
-                pixels[index(pos, img.width as usize, pixel_width) + i as usize] =
-                    color_by_width(color, pixel_width, i)
-            }
+            let i = index(pos, img.width as usize, 3);
+            pixels[i .. i + 3].copy_from_slice(&[r, g, b])
+        },
+        Pixels::RGBA8(ref mut pixels) => {
+            let i = index(pos, img.width as usize, 4);
+            pixels[i .. i + 4].copy_from_slice(&[r, g, b, 255])
         }
-        //_ => panic!("only RGB8 and RGBA8 modes are supported")
-    }
-}
-
-fn color_by_width((r,g,b): ColorRGB8, pixel_width: u8, channel_index: u8) -> u8 {
-    match (pixel_width, channel_index) {
-        // grayscale
-        (1, _) => (r+g+b) / 3,
-        // grayscale with alpha channel
-        (2, 0) => (r+g+b) / 3,
-        (2, 1) => 255u8,
-        // RGB
-        (3, 0) => r,
-        (3, 1) => g,
-        (3, 2) => b,
-        // RGB with alpha channel
-        (4, 0) => r,
-        (4, 1) => g,
-        (4, 2) => b,
-        (4, 3) => 255u8,
-        (_, _) => panic!("invalid pixel_width / channel_index combination")
     }
 }
 
@@ -137,27 +192,105 @@ fn index((x,y): (usize,usize), width: usize, bytes_per_color: u8) -> usize {
     y * width * bytes_per_color as usize + x * bytes_per_color as usize
 }
 
-pub fn save(map: &Map, search: &Search, dest: String) {
-    let mut img = map_to_png(map);
-    draw_points(&search.visited, GRAY, &mut img);
-    draw_points(&search.paths[0], WHITE, &mut img);
-    draw_points(&search.start, GREEN, &mut img);
-    draw_points(&search.goals, RED, &mut img);
-    write_image(&mut img, &dest);
+pub fn save(map: &Map, search: &Search, dest: String) -> Result<(), Error> {
+    save_as(map, search, &dest, Format::default(), &Legend::default())
 }
 
-pub fn write_image(img: &mut Image, dest: &str) -> () {
+/// Draw the search result on the map in the colours of the legend
+/// and write it in the given format.
+pub fn save_as(map: &Map, search: &Search, dest: &str,
+               format: Format, legend: &Legend) -> Result<(), Error> {
+    let mut img = map_to_png_with(map, legend);
+    img.format = format;
+    draw_points(&search.visited, legend.visited, &mut img);
+    if let Some (path) = search.paths.first() {
+        draw_points(path, legend.path, &mut img);
+    }
+    draw_points(&search.start, legend.color(Field::Start), &mut img);
+    draw_points(&search.goals, legend.color(Field::Goal), &mut img);
+    write_image(&mut img, dest)
+}
+
+/// Write the image in its format.
+/// Bit depths below 8 are written as 8-bit grey, and palettes fall back to RGB
+/// if the image has more than 256 colours.
+pub fn write_image(img: &mut Image, dest: &str) -> Result<(), Error> {
     let path = std::path::Path::new(dest);
-    let file = std::fs::File::create(path).unwrap();
+    let file = std::fs::File::create(path)?;
     let ref mut w = std::io::BufWriter::new(file);
 
+    let colors: Vec<(u8,u8,u8,u8)> = match img.pixels {
+        Pixels::RGB8(ref data) => data.chunks(3).map(|p| (p[0], p[1], p[2], 255)).collect(),
+        Pixels::RGBA8(ref data) => data.chunks(4).map(|p| (p[0], p[1], p[2], p[3])).collect()
+    };
     let mut encoder = png::Encoder::new(w, img.width, img.height);
-    encoder.set_color(png::ColorType::RGB);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+    let Format { color_type, bit_depth } = img.format;
+    let data = match (color_type, index_colors(&colors)) {
+        (png::ColorType::Indexed, Some ((palette, indices))) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_palette(palette);
+            indices
+        },
+        (png::ColorType::Indexed, None) => {
+            encoder.set_color(png::ColorType::RGB);
+            encoder.set_depth(png::BitDepth::Eight);
+            samples(&colors, png::ColorType::RGB, png::BitDepth::Eight)
+        },
+        _ => {
+            let bit_depth = match bit_depth {
+                png::BitDepth::Sixteen => png::BitDepth::Sixteen,
+                _ => png::BitDepth::Eight
+            };
+            encoder.set_color(color_type);
+            encoder.set_depth(bit_depth);
+            samples(&colors, color_type, bit_depth)
+        }
+    };
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok (())
+}
+
+/// Build a palette of the colours, if there are few enough of them,
+/// and index the colours into it.
+fn index_colors(colors: &[(u8,u8,u8,u8)]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut palette: Vec<ColorRGB8> = vec![];
+    let mut indices = Vec::with_capacity(colors.len());
+    for &(r,g,b,_) in colors {
+        let i = match palette.iter().position(|&c| c == (r,g,b)) {
+            Some (i) => i,
+            None if palette.len() < 256 => {
+                palette.push((r,g,b));
+                palette.len() - 1
+            },
+            None => return None
+        };
+        indices.push(i as u8)
+    }
+    Some ((palette.into_iter().flat_map(|(r,g,b)| vec![r, g, b]).collect(), indices))
+}
+
+/// Lay the colours out as samples of a grey or RGB colour type, with or without alpha.
+fn samples(colors: &[(u8,u8,u8,u8)], color_type: png::ColorType,
+           bit_depth: png::BitDepth) -> Vec<u8> {
+    let channels = |&(r,g,b,a): &(u8,u8,u8,u8)| match color_type {
+        png::ColorType::Grayscale => vec![luma((r,g,b))],
+        png::ColorType::GrayscaleAlpha => vec![luma((r,g,b)), a],
+        png::ColorType::RGBA => vec![r, g, b, a],
+        _ => vec![r, g, b]
+    };
+    let bytes = if bit_depth == png::BitDepth::Sixteen { 2 } else { 1 };
+    // A 16-bit sample of v * 257 is just v in both of its big-endian bytes.
+    colors.iter()
+        .flat_map(channels)
+        .flat_map(|v| std::iter::repeat_n(v, bytes))
+        .collect()
+}
 
-    let Pixels::RGB8(ref data) = img.pixels;
-    writer.write_image_data(&data).unwrap();
+/// Grey level of a colour which keeps the basic colours of maps apart.
+fn luma((r,g,b): ColorRGB8) -> u8 {
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8
 }
 
 #[cfg(test)]
@@ -173,12 +306,108 @@ mod tests {
         let mut image1 = super::Image {
             width: 3,
             height: 2,
-            pixels: super::Pixels::RGB8(data1)
+            pixels: super::Pixels::RGB8(data1),
+            format: super::Format::default()
+        };
+        super::write_image(&mut image1, TEST_IMAGE).unwrap();
+        let image2 = super::load_image(TEST_IMAGE).unwrap();
+        match image2.pixels {
+            super::Pixels::RGB8(data2) => assert_eq!(&expected[..], &data2[..]),
+            other => panic!("expected RGB8 pixels, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        match super::load("test/no-such-map.png") {
+            Err (crate::Error::Io(e)) => assert_eq!(std::io::ErrorKind::NotFound, e.kind()),
+            _ => panic!("expected an I/O error")
+        }
+    }
+
+    #[test]
+    fn grey_levels_round_trip_as_weights() {
+        use super::{ color_to_weight, weight_to_color, BLACK, GRAY, WHITE };
+        use crate::map::legend::Legend;
+        use crate::map::{ Field, MAX_WEIGHT };
+        let legend = Legend::default();
+        assert_eq!(Field::Passable, legend.field(BLACK));
+        assert_eq!(Field::Passable, legend.field(WHITE));
+        assert_eq!(Field::Passable, legend.field(GRAY));
+        for weight in 1 ..= MAX_WEIGHT {
+            let color = weight_to_color(weight);
+            assert_eq!(Some (weight), color_to_weight(color));
+            assert!(![BLACK, GRAY, WHITE].contains(&color));
+        }
+        assert_eq!(None, color_to_weight(GRAY));
+        assert_eq!(None, color_to_weight(WHITE));
+    }
+
+    #[test]
+    fn saved_results_reload_without_weights() {
+        use crate::map::Field;
+        let map = super::load("../test/fixtures/map3.png").unwrap();
+        let search = crate::astar(map.start(), map.goals(), &map).unwrap();
+        let dest = std::env::temp_dir().join("map-result.png");
+        let dest = dest.to_str().unwrap();
+        super::save(&map, &search, dest.to_string()).unwrap();
+        let reloaded = super::load(dest).unwrap();
+        assert_eq!(map.start(), reloaded.start());
+        assert_eq!(map.goals(), reloaded.goals());
+        assert!(reloaded.positions().all(|pos| !matches!(reloaded[pos], Field::Weighted(_))));
+    }
+
+    #[test]
+    fn saves_results_without_paths() {
+        let map = super::load("../test/fixtures/map3.png").unwrap();
+        let search = crate::Search { start: map.start(), goals: map.goals(), paths: vec![],
+                                     visited: vec![], stats: Default::default() };
+        let dest = std::env::temp_dir().join("map-no-path.png");
+        super::save(&map, &search, dest.to_str().unwrap().to_string()).unwrap();
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        use super::{ Format, Image, Pixels };
+        use png::{ BitDepth, ColorType };
+        let data = vec![255, 0, 0,   0, 255, 0,   0, 0, 255,   0, 0, 0];
+        for &(color_type, bit_depth) in &[(ColorType::RGBA, BitDepth::Eight),
+                                          (ColorType::RGB, BitDepth::Sixteen),
+                                          (ColorType::Indexed, BitDepth::Eight)] {
+            let dest = std::env::temp_dir().join(format!("map-{:?}-{:?}.png", color_type, bit_depth));
+            let dest = dest.to_str().unwrap();
+            let format = Format { color_type, bit_depth };
+            let mut image = Image { width: 2, height: 2, pixels: Pixels::RGB8(data.clone()), format };
+            super::write_image(&mut image, dest).unwrap();
+            let read = super::load_image(dest).unwrap();
+            assert_eq!(format, read.format);
+            let rgb: Vec<u8> = match read.pixels {
+                Pixels::RGB8(p) => p,
+                Pixels::RGBA8(p) => p.chunks(4).flat_map(|p| p[.. 3].to_vec()).collect()
+            };
+            assert_eq!(data, rgb);
+        }
+    }
+
+    #[test]
+    fn transparency_decides_fields() {
+        use super::{ Format, Image, Pixels, Transparency };
+        use crate::map::legend::Legend;
+        use crate::map::Field;
+        let dest = std::env::temp_dir().join("map-transparency.png");
+        let dest = dest.to_str().unwrap();
+        let mut image = Image { width: 2, height: 1,
+                                pixels: Pixels::RGBA8(vec![0, 0, 0, 0,   0, 0, 255, 0]),
+                                format: Format { color_type: png::ColorType::RGBA,
+                                                 ..Format::default() } };
+        super::write_image(&mut image, dest).unwrap();
+        let fields = |transparent| {
+            let legend = Legend { transparent, ..Legend::default() };
+            super::load_with(dest, &legend).unwrap().0.fields
         };
-        super::write_image(&mut image1, TEST_IMAGE);
-        let image2 = super::load_image(TEST_IMAGE);
-        let super::Pixels::RGB8(data2) = image2.pixels;
-        assert_eq!(&expected[..], &data2[..]);
+        assert_eq!(vec![Field::Impassable, Field::Impassable], fields(Transparency::Impassable));
+        assert_eq!(vec![Field::Passable, Field::Passable], fields(Transparency::Passable));
+        assert_eq!(vec![Field::Passable, Field::Impassable], fields(Transparency::Ignore));
     }
 
 }
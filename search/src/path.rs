@@ -0,0 +1,141 @@
+//! Post-processing of found paths.
+//!
+//! Searches return paths goal first, as they're built by following steps back from the goal.
+//! Grid searches return a position for every field moved through, any-angle searches only waypoints.
+
+use crate::heuristic::Heuristic;
+use crate::map::{ Field, Map, Position };
+use crate::{ step_cost, Path };
+
+/// Order the path from a start field to a goal, reversing it if it ends at a start field.
+pub fn normalise(map: &Map, mut path: Path) -> Path {
+    let is_start = |pos: Option<&Position>| pos.is_some_and(|&pos| map[pos] == Field::Start);
+    if is_start(path.last()) && !is_start(path.first()) {
+        path.reverse();
+    }
+    path
+}
+
+/// Drop duplicate points and points which lie on a straight line between their neighbours.
+pub fn remove_collinear(path: &[Position]) -> Path {
+    let mut result: Path = vec![];
+    for &pos in path {
+        if result.last() == Some (&pos) {
+            continue
+        }
+        if result.len() >= 2 {
+            let (a, b) = (result[result.len() - 2], result[result.len() - 1]);
+            let (ux, uy) = (b.0 as isize - a.0 as isize, b.1 as isize - a.1 as isize);
+            let (vx, vy) = (pos.0 as isize - b.0 as isize, pos.1 as isize - b.1 as isize);
+            // Same direction: no turn and no turning back.
+            if ux * vy == uy * vx && ux * vx + uy * vy > 0 {
+                result.pop();
+            }
+        }
+        result.push(pos);
+    }
+    result
+}
+
+/// Whether two positions are joined by a line in the map's plane rather than across a wrapped edge.
+fn in_plane(map: &Map, a: Position, b: Position) -> bool {
+    map.delta(a, b) == (a.0.abs_diff(b.0), a.1.abs_diff(b.1))
+}
+
+/// String pulling: keep only the waypoints needed to follow the path in straight lines,
+/// going from each waypoint straight to the farthest later point it can see.
+pub fn smooth(map: &Map, path: &[Position]) -> Path {
+    let mut result: Path = path.iter().take(1).cloned().collect();
+    let mut anchor = 0;
+    while anchor + 1 < path.len() {
+        let mut next = anchor + 1;
+        for candidate in (anchor + 2 .. path.len()).rev() {
+            if in_plane(map, path[anchor], path[candidate])
+                && map.line_of_sight(path[anchor], path[candidate]) {
+                next = candidate;
+                break
+            }
+        }
+        result.push(path[next]);
+        anchor = next;
+    }
+    result
+}
+
+/// Put every field crossed between consecutive waypoints into the path,
+/// so that each step moves to a neighbouring field.
+pub fn densify(map: &Map, path: &[Position]) -> Path {
+    let mut result: Path = path.iter().take(1).cloned().collect();
+    for segment in path.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        if in_plane(map, a, b) {
+            result.extend(map.line(a, b).into_iter().skip(1));
+        } else {
+            // Neighbours across a wrapped edge; no other waypoints should be that way.
+            result.push(b);
+        }
+    }
+    result
+}
+
+/// Length of the path with each segment measured by `metric`,
+/// e.g. `Euclidean` for any-angle paths or `Octile` for paths of 8-connected moves.
+pub fn length(map: &Map, path: &[Position], metric: &dyn Heuristic) -> f64 {
+    path.windows(2)
+        .map(|segment| metric.estimate(map.delta(segment[0], segment[1])))
+        .sum()
+}
+
+/// Cost of moving along the path field by field, charged like searches do:
+/// the cost of each entered field, times the square root of 2 for diagonal moves.
+pub fn cost(map: &Map, path: &[Position]) -> f64 {
+    densify(map, path).windows(2)
+        .map(|step| step_cost(map, step[0], step[1]))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::graph::GraphSearch;
+    use crate::heuristic::{ Euclidean, Octile };
+    use crate::map::{ self, ascii };
+
+    #[test]
+    fn straightens_grid_paths() {
+        let map = ascii::parse("S....\n.....\n..#..\n....G\n", &ascii::Glyphs::default()).unwrap();
+        let found = crate::astar_search(map.clone()).run(None).unwrap();
+        let path = normalise(&map, found.clone());
+        assert_eq!(Some (&(0,0)), path.first());
+        assert_eq!(Some (&(4,3)), path.last());
+        assert_eq!(path, normalise(&map, path.clone()));
+        let corners = remove_collinear(&path);
+        assert!(corners.len() <= path.len());
+        assert_eq!(densify(&map, &corners), path);
+        let smoothed = smooth(&map, &path);
+        assert!(smoothed.windows(2).all(|s| map.line_of_sight(s[0], s[1])));
+        assert!(length(&map, &smoothed, &Euclidean) <= length(&map, &path, &Octile) + 1e-9);
+        assert!((cost(&map, &path) - length(&map, &path, &Octile)).abs() < 1e-9);
+        assert_eq!(path, densify(&map, &path));
+    }
+
+    #[test]
+    fn collinear_points_are_dropped() {
+        let path = vec![(0,0), (1,1), (2,2), (2,2), (3,2), (4,2), (4,1), (4,2)];
+        assert_eq!(vec![(0,0), (2,2), (4,2), (4,1), (4,2)], remove_collinear(&path));
+    }
+
+    #[test]
+    fn smoothed_paths_cross_only_passable_fields() {
+        let map = map::png::load("../test/fixtures/map3.png").unwrap();
+        let path = normalise(&map, crate::astar_search(map.clone()).run(None).unwrap());
+        let smoothed = smooth(&map, &path);
+        let dense = densify(&map, &smoothed);
+        assert!(dense.iter().all(|&pos| map[pos].is_passable()));
+        assert_eq!(path.first(), dense.first());
+        assert_eq!(path.last(), dense.last());
+        assert!(length(&map, &smoothed, &Euclidean) <= length(&map, &path, &Euclidean) + 1e-9);
+    }
+
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+use std::time::{ Duration, Instant };
+
+/// Counters maintained by every search algorithm.
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    /// Nodes taken off the frontier and expanded.
+    pub expanded: usize,
+    /// Nodes put on the frontier, including the start nodes.
+    pub generated: usize,
+    /// Largest size the frontier reached.
+    pub max_frontier: usize,
+    /// Already expanded nodes put back on the frontier after a cheaper path to them was found.
+    pub reopened: usize,
+    /// Number of moves in the found path.
+    pub path_length: usize,
+    /// Total cost of the moves in the found path.
+    pub path_cost: f64,
+    /// Time spent searching.
+    pub wall_time: Duration,
+    /// Number of search steps taken.
+    pub steps: usize,
+    /// Bounds of the iterations of iterative deepening searches, in the order they were tried:
+    /// depth limits or f-cost thresholds. Empty for other searches.
+    pub thresholds: Vec<f64>
+}
+
+impl SearchStats {
+
+    pub fn frontier_size(&mut self, size: usize) {
+        self.max_frontier = self.max_frontier.max(size);
+    }
+
+    pub fn path<Id>(&mut self, path: &[Id], cost: f64) {
+        self.path_length = path.len().saturating_sub(1);
+        self.path_cost = cost;
+    }
+
+    /// Account for one step which started at `started`.
+    pub fn step(&mut self, started: Instant) {
+        self.steps += 1;
+        self.wall_time += started.elapsed();
+    }
+
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "expanded    : {}", self.expanded)?;
+        writeln!(f, "generated   : {}", self.generated)?;
+        writeln!(f, "max frontier: {}", self.max_frontier)?;
+        writeln!(f, "reopened    : {}", self.reopened)?;
+        writeln!(f, "path length : {}", self.path_length)?;
+        writeln!(f, "path cost   : {:.3}", self.path_cost)?;
+        writeln!(f, "wall time   : {:?}", self.wall_time)?;
+        write!(f, "steps       : {}", self.steps)?;
+        if !self.thresholds.is_empty() {
+            let thresholds: Vec<String> = self.thresholds.iter().map(|t| format!("{:.3}", t)).collect();
+            write!(f, "\nthresholds  : {}", thresholds.join(", "))?;
+        }
+        Ok (())
+    }
+}
@@ -0,0 +1,262 @@
+//! Any-angle search: Theta* and Lazy Theta* find paths of straight lines between
+//! fields which see each other, rather than chains of moves between neighbours.
+
+use crate::graph::*;
+use crate::heuristic::{ Euclidean, Heuristic };
+use crate::map::{ Field, Map, Position, WorldShape };
+use crate::stats::SearchStats;
+use crate::{ allowed_moves, Error, MapField };
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Check that the map's fields can be connected by straight lines
+/// whose cost is their length.
+pub fn check_map(map: &Map) -> Result<(), Error> {
+    let unsupported = |reason: &str| Err (Error::UnsupportedMap(reason.to_string()));
+    if map.rules.shape != WorldShape::Bounded {
+        return unsupported("any-angle search needs a bounded map")
+    }
+    if map.fields.iter().any(|f| f.is_passable() && f.cost() != 1.0) {
+        return unsupported("any-angle search needs all passable fields to cost the same")
+    }
+    Ok (())
+}
+
+/// Step-wise Theta*: A* which links each reached field to the parent of the field it was reached from
+/// whenever the two see each other.
+///
+/// Lazy Theta* assumes they do and only checks the line of sight once the field is expanded,
+/// falling back to the best expanded neighbour, which saves most of the checks.
+///
+/// Paths are the waypoints where they change direction, goal first;
+/// their cost is their Euclidean length, so maps with weighted fields are rejected.
+#[derive(Clone)]
+pub struct ThetaStarSearch {
+    pub result: SearchState<MapField>,
+    pub map: Rc<Map>,
+    pub lazy: bool,
+    pub goals: Vec<Position>,
+    pub frontier: BinaryHeap<Scored<Position>>,
+    pub visited: HashSet<Position>,
+    /// Field each reached field is seen from on the best known path; start fields have none.
+    pub parents: HashMap<Position, Position>,
+    pub g_score: HashMap<Position, f64>,
+    pub stats: SearchStats
+}
+
+impl ThetaStarSearch {
+
+    pub fn new(map: Rc<Map>, lazy: bool) -> ThetaStarSearch {
+        let goals = map.goals();
+        let mut search = ThetaStarSearch { result: SearchState::NotStarted,
+                                           lazy,
+                                           goals,
+                                           frontier: BinaryHeap::new(),
+                                           visited: HashSet::new(),
+                                           parents: HashMap::new(),
+                                           g_score: HashMap::new(),
+                                           stats: SearchStats::default(),
+                                           map };
+        for pos in search.map.start() {
+            search.g_score.insert(pos, 0.0);
+            search.frontier.push(Scored(search.estimate(pos), pos));
+        }
+        search.stats.generated = search.frontier.len();
+        search.stats.max_frontier = search.frontier.len();
+        search
+    }
+
+    fn distance(&self, from: Position, to: Position) -> f64 {
+        Euclidean.estimate(self.map.delta(from, to))
+    }
+
+    fn estimate(&self, pos: Position) -> f64 {
+        self.goals.iter()
+            .map(|&goal| self.distance(pos, goal))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Euclidean length of the found path.
+    pub fn length(&self) -> Option<f64> {
+        match self.result {
+            SearchState::Finished(_) => Some (self.stats.path_cost),
+            _ => None
+        }
+    }
+
+    /// Pop the best open field, skipping entries superseded by a shorter path.
+    fn pop_open(&mut self) -> Option<Position> {
+        while let Some (Scored(_, pos)) = self.frontier.pop() {
+            if !self.visited.contains(&pos) {
+                return Some (pos)
+            }
+        }
+        None
+    }
+
+    /// Make sure a field assumed to see its parent does, otherwise connect it to
+    /// the expanded neighbour it's reached from the soonest.
+    fn check_parent(&mut self, pos: Position) {
+        let parent = match self.parents.get(&pos) {
+            Some (&parent) => parent,
+            None => return
+        };
+        if self.map.line_of_sight(parent, pos) {
+            return
+        }
+        let best = allowed_moves(&self.map, pos).into_iter()
+            .filter(|neighbour| self.visited.contains(neighbour))
+            .map(|neighbour| (self.g_score[&neighbour] + self.distance(neighbour, pos), neighbour))
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some ((g, neighbour)) = best {
+            self.g_score.insert(pos, g);
+            self.parents.insert(pos, neighbour);
+        }
+    }
+
+    fn expand(&mut self) {
+        let current = match self.pop_open() {
+            None => {
+                self.result = SearchState::Failed(Error::GoalUnreachable);
+                return
+            },
+            Some (current) => current
+        };
+        self.result = SearchState::InProgress;
+        if self.lazy {
+            self.check_parent(current);
+        }
+        debug!(target: "theta", "current: {:?}", current);
+        if self.map[current] == Field::Goal {
+            debug!(target: "theta", "goal found: {:?}", current);
+            let path = build_path::<MapField>(&self.parents, current);
+            self.stats.path(&path, self.g_score[&current]);
+            self.result = SearchState::Finished(path);
+            return
+        }
+        self.visited.insert(current);
+        self.stats.expanded += 1;
+        let parent = self.parents.get(&current).cloned();
+        for next in allowed_moves(&self.map, current) {
+            if self.visited.contains(&next) {
+                continue
+            }
+            // Lazy Theta* puts off checking the line of sight until `next` is expanded.
+            let (from, tentative_g) = match parent {
+                Some (parent) if self.lazy || self.map.line_of_sight(parent, next) =>
+                    (parent, self.g_score[&parent] + self.distance(parent, next)),
+                _ => (current, self.g_score[&current] + self.distance(current, next))
+            };
+            let improved = match self.g_score.get(&next) {
+                None => true,
+                Some (&known) => tentative_g < known - 1e-9
+            };
+            if improved {
+                self.g_score.insert(next, tentative_g);
+                self.parents.insert(next, from);
+                self.frontier.push(Scored(tentative_g + self.estimate(next), next));
+                self.stats.generated += 1;
+            }
+        }
+        self.stats.frontier_size(self.frontier.len());
+    }
+
+}
+
+impl GraphSearch<Node2d> for ThetaStarSearch {
+
+    type Node = MapField;
+
+    fn step(&mut self) {
+        if self.result.is_over() {
+            return
+        }
+        let started = Instant::now();
+        self.expand();
+        self.stats.step(started);
+    }
+
+    fn result(&self) -> &SearchState<MapField> {
+        &self.result
+    }
+
+    fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    fn nodes(&self) -> Box<dyn Iterator<Item=Node2d> + '_> {
+        let visited = self.visited.iter()
+            .map(|&pos| Node2d(pos, NodeState::Visited));
+        let frontier = self.frontier.iter()
+            .map(|&Scored(_, pos)| Node2d(pos, NodeState::Frontier));
+        if let SearchState::Finished(ref path) = self.result {
+            // Show the lines between waypoints, not just the waypoints.
+            let path = path.windows(2)
+                .flat_map(move |segment| self.map.line(segment[0], segment[1]))
+                .chain(path.first().cloned())
+                .map(|pos| Node2d(pos, NodeState::Path));
+            Box::new( visited.chain(frontier).chain(path) )
+        } else {
+            Box::new( visited.chain(frontier) )
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::graph::GraphSearch;
+    use crate::map::{ self, ascii, generate, Connectivity, WorldShape };
+    use crate::Error;
+
+    #[test]
+    fn open_areas_are_crossed_in_a_straight_line() {
+        let map = generate::open(30, 20);
+        for mut search in [crate::theta_star(map.clone()), crate::lazy_theta_star(map.clone())] {
+            let path = search.run(None).unwrap();
+            assert_eq!(vec![(29,19), (0,0)], path);
+            assert!((search.length().unwrap() - (29f64 * 29.0 + 19.0 * 19.0).sqrt()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn paths_are_no_longer_than_grid_paths() {
+        for fixture in &["map1", "map2", "map3"] {
+            let mut map = map::png::load(&format!("../test/fixtures/{}.png", fixture)).unwrap();
+            map.rules.connectivity = Connectivity::EightNoCornerCutting;
+            let mut astar = crate::astar_search(map.clone());
+            astar.run(None).unwrap();
+            for mut search in [crate::theta_star(map.clone()), crate::lazy_theta_star(map.clone())] {
+                let path = search.run(None).unwrap();
+                assert!(search.stats.path_cost <= astar.stats.path_cost + 1e-9, "{}", fixture);
+                assert_eq!(map.goals(), vec![path[0]]);
+                assert_eq!(map.start(), vec![path[path.len() - 1]]);
+                assert!(path.windows(2).all(|segment| map.line_of_sight(segment[0], segment[1])));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrapping_maps() {
+        let mut map = generate::open(5, 5);
+        map.rules.shape = WorldShape::Torus;
+        match crate::theta_star(map).run(None) {
+            Err (Error::UnsupportedMap(_)) => (),
+            _ => panic!("expected UnsupportedMap")
+        }
+    }
+
+    #[test]
+    fn rejects_weighted_maps() {
+        let map = ascii::parse("S5G\n", &ascii::Glyphs::default()).unwrap();
+        for mut search in [crate::theta_star(map.clone()), crate::lazy_theta_star(map)] {
+            match search.run(None) {
+                Err (Error::UnsupportedMap(_)) => (),
+                _ => panic!("expected UnsupportedMap")
+            }
+        }
+    }
+
+}
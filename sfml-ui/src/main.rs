@@ -8,9 +8,11 @@ extern crate sfml;
 mod frame_counter;
 
 use frame_counter::{ FrameCounter, FrameUpdate };
-use search::bfs::BFSSearch;
 use search::graph::{ GraphSearch, Node2d, NodeState };
-use search::{ map, MapField };
+use search::map::legend::Legend;
+use search::map::png::ColorRGB8;
+use search::incremental::IncrementalSearch;
+use search::{ heuristic, map };
 use sfml::graphics::{
     Color,
     Drawable,
@@ -22,20 +24,60 @@ use sfml::graphics::{
     VertexArray,
     View
 };
-use sfml::system::Vector2f;
-use sfml::window::{ Event, Key, VideoMode, ContextSettings };
+use sfml::system::{ Vector2f, Vector2i };
+use std::collections::HashSet;
+use sfml::window::{ mouse, Event, Key, VideoMode, ContextSettings };
 
 fn main() {
     env_logger::init();
     let args : Vec<String> = std::env::args().collect();
     if args.len() < 2
-        { panic!("expected MAP") }
+        { panic!("expected MAP [METHOD [HEURISTIC [LEGEND]]]") }
     let ref arg_map = args[1];
+    let method = args.get(2).map(|m| m.as_str()).unwrap_or("bfs");
+    let heuristic = match args.get(3) {
+        None => Box::new(heuristic::Octile),
+        Some (name) => heuristic::by_name(name)
+            .unwrap_or_else(|| panic!("unknown heuristic: {}", name))
+    };
+
+    let legend = match args.get(4) {
+        None => Legend::default(),
+        Some (source) => Legend::load(source)
+            .unwrap_or_else(|e| panic!("{}: {}", source, e))
+    };
 
-    let map = map::png::load(arg_map);
-    let search_method = search::bfs as fn(search::map::Map) -> BFSSearch<MapField>;
+    let (map, _) = map::png::load_with(arg_map, &legend)
+        .unwrap_or_else(|e| panic!("{}: {}", arg_map, e));
+    match method {
+        "bfs"      => run(&map, &legend, search::bfs(map.clone())),
+        "astar"    => run(&map, &legend, search::astar_with(map.clone(), heuristic)),
+        "dijkstra" => run(&map, &legend, search::dijkstra(map.clone())),
+        "greedy"   => run(&map, &legend, search::greedy_with(map.clone(), heuristic)),
+        "dfs"      => run(&map, &legend, search::dfs(map.clone())),
+        "iddfs"    => run(&map, &legend, search::iddfs(map.clone())),
+        "idastar"  => run(&map, &legend, search::idastar_with(map.clone(), heuristic)),
+        "bibfs"    => run(&map, &legend, search::bidirectional_bfs(map.clone())),
+        "biastar"  => run(&map, &legend, search::bidirectional_astar_with(map.clone(), heuristic)),
+        "jps"      => run(&map, &legend, search::jps(map.clone())),
+        "jps-plus" => run(&map, &legend, search::jps_plus(map.clone())),
+        "theta"    => run(&map, &legend, search::theta_star(map.clone())),
+        "lazy-theta" => run(&map, &legend, search::lazy_theta_star(map.clone())),
+        // Clicking a field blocks or clears it and lets the search repair its path.
+        "lpastar"  => run_with(&map, &legend, search::lpa_star(map.clone()),
+                               Some (IncrementalSearch::toggle_field)),
+        "dstar-lite" => run_with(&map, &legend, search::dstar_lite(map.clone()),
+                                 Some (IncrementalSearch::toggle_field)),
+        _          => panic!("unknown search method: {}", method)
+    }
+}
+
+fn run<S: GraphSearch<Node2d> + Clone>(map: &map::Map, legend: &Legend, search: S) {
+    run_with(map, legend, search, None)
+}
 
-    let search = search_method(map.clone());
+fn run_with<S: GraphSearch<Node2d> + Clone>(map: &map::Map, legend: &Legend, search: S,
+                                            toggle: Option<fn(&mut S, map::Position) -> Result<map::Field, search::Error>>) {
     let mut fc = FrameCounter::from_fps(20);
     let (w, h) = (map.width as u32, map.height as u32);
     let mut app = AppState {
@@ -43,12 +85,16 @@ fn main() {
         single_step: false,
         search: search,
         saved_search: None,
+        toggle: toggle,
+        walls: map.positions().filter(|&pos| map[pos] == map::Field::Impassable).collect(),
+        size: (w, h),
         window: create_window(w, h)
     };
 
-    let mut snapshot = SearchSnapshot::new((w, h));
-    snapshot.update(&app.search);
-    app.window.clear(Color::BLACK);
+    let background = to_color(legend.color(map::Field::Passable));
+    let mut snapshot = SearchSnapshot::new((w, h), legend);
+    snapshot.update(&app.search, &app.walls);
+    app.window.clear(background);
     app.save();
 
     while app.window.is_open() {
@@ -64,8 +110,8 @@ fn main() {
         }
         if let FrameUpdate::NewFrame{elapsed_frames: fs, elapsed_ns: ns} = fc.update() {
             info!(target: "tick", "new frame: ms={:?} skipped={:?}", ns / 1_000_000, fs - 1);
-            snapshot.update(&app.search);
-            app.window.clear(Color::BLACK);
+            snapshot.update(&app.search, &app.walls);
+            app.window.clear(background);
             app.window.draw(&snapshot);
             app.window.display();
         }
@@ -73,28 +119,43 @@ fn main() {
 }
 
 struct SearchSnapshot {
-    vertices: VertexArray
+    vertices: VertexArray,
+    visited: Color,
+    frontier: Color,
+    path: Color,
+    backward_visited: Color,
+    backward_frontier: Color,
+    wall: Color
 }
 
-const GRAY: sfml::graphics::Color = Color{ r: 90, g: 90, b: 90, a: 255 };
-
 impl SearchSnapshot {
 
-    fn new((width, height): (u32, u32)) -> SearchSnapshot {
+    fn new((width, height): (u32, u32), legend: &Legend) -> SearchSnapshot {
         let size = width * height;
         // allocate in one go
         let va = VertexArray::new(PrimitiveType::Points, size as usize);
-        SearchSnapshot { vertices: va }
+        SearchSnapshot { vertices: va,
+                         visited: to_color(legend.visited),
+                         frontier: to_color(legend.frontier),
+                         path: to_color(legend.path),
+                         backward_visited: to_color(legend.backward_visited),
+                         backward_frontier: to_color(legend.backward_frontier),
+                         wall: to_color(legend.color(map::Field::Impassable)) }
     }
 
-    fn update(&mut self, search: &impl GraphSearch<Node2d>) {
+    fn update(&mut self, search: &impl GraphSearch<Node2d>, walls: &HashSet<map::Position>) {
         // TODO: try not to redraw the whole buffer each frame
         self.vertices.clear();
+        for &pos in walls {
+            self.vertices.append(&pos_to_vertex(pos, self.wall));
+        }
         for Node2d(pos, state) in search.nodes() {
             let color = match state {
-                NodeState::Visited => GRAY,
-                NodeState::Frontier => Color::RED,
-                NodeState::Path => Color::WHITE
+                NodeState::Visited => self.visited,
+                NodeState::Frontier => self.frontier,
+                NodeState::Path => self.path,
+                NodeState::BackwardVisited => self.backward_visited,
+                NodeState::BackwardFrontier => self.backward_frontier
             };
             self.vertices.append(&pos_to_vertex(pos, color));
         }
@@ -102,6 +163,10 @@ impl SearchSnapshot {
 
 }
 
+fn to_color((r, g, b): ColorRGB8) -> Color {
+    Color::rgb(r, g, b)
+}
+
 fn pos_to_vertex((x, y): (usize, usize), color: Color) -> Vertex {
     let v2f = Vector2f::new(x as f32, y as f32);
     Vertex::with_pos_color(v2f, color)
@@ -119,15 +184,20 @@ impl Drawable for SearchSnapshot {
 
 }
 
-struct AppState {
+struct AppState<S: GraphSearch<Node2d> + Clone> {
     pause: bool,
     single_step: bool,
-    search: BFSSearch<MapField>,
+    search: S,
     window: RenderWindow,
-    saved_search: Option<BFSSearch<MapField>>
+    saved_search: Option<(S, HashSet<map::Position>)>,
+    /// Changes the field at a position, for searches which can handle that, returning the new field.
+    toggle: Option<fn(&mut S, map::Position) -> Result<map::Field, search::Error>>,
+    /// Impassable fields, kept up to date with toggled ones.
+    walls: HashSet<map::Position>,
+    size: (u32, u32)
 }
 
-impl AppState {
+impl<S: GraphSearch<Node2d> + Clone> AppState<S> {
 
     fn process_input_event(&mut self, e: &Event) {
         info!(target: "events", "event: {:?}", e);
@@ -146,6 +216,7 @@ impl AppState {
                 Key::R      => self.restore(),
                 _           => info!(target: "events", "unhandled key pressed: {:?}", code)
             },
+            &Event::MouseButtonPressed{button: mouse::Button::Left, x, y} => self.toggle_field(x, y),
             _ => {}
         }
     }
@@ -161,14 +232,34 @@ impl AppState {
         info!(target: "events", "zoom by {:?}", factor);
     }
 
+    fn toggle_field(&mut self, x: i32, y: i32) {
+        let toggle = match self.toggle {
+            Some (toggle) => toggle,
+            None => return info!(target: "events", "search can't handle field changes")
+        };
+        let coords = self.window.map_pixel_to_coords_current_view(Vector2i::new(x, y));
+        let (w, h) = self.size;
+        if coords.x < 0.0 || coords.y < 0.0 || coords.x >= w as f32 || coords.y >= h as f32 {
+            return
+        }
+        let pos = (coords.x as usize, coords.y as usize);
+        match toggle(&mut self.search, pos) {
+            Ok (map::Field::Impassable) => self.walls.insert(pos),
+            Ok (_) => self.walls.remove(&pos),
+            Err (e) => return info!(target: "events", "can't toggle field {:?}: {}", pos, e)
+        };
+        info!(target: "events", "toggled field {:?}", pos);
+    }
+
     fn save(&mut self) {
-        self.saved_search = Some (self.search.clone());
+        self.saved_search = Some ((self.search.clone(), self.walls.clone()));
         info!(target: "events", "saved search state");
     }
 
     fn restore(&mut self) {
-        if let Some (ref saved) = self.saved_search {
+        if let Some ((ref saved, ref walls)) = self.saved_search {
             self.search = saved.clone();
+            self.walls = walls.clone();
             self.window.clear(Color::BLACK);
             info!(target: "events", "restored search state");
         } else {